@@ -1,19 +1,160 @@
 use crate::{
-    block::block_kind,
-    game::{
-        fix_block, hard_drop, hold, move_block, rotate_right, Field, Game, Position, FIELD_HEIGHT,
-        FIELD_WIDTH,
+    block::{block_kind, BlockShape, BLOCK_SIZE},
+    field::{
+        block_mask, drop_pos, erase_bits, is_collision_bits, place_bits, Bitboard, BlockMask,
+        EMPTY_ROW, FIELD_HEIGHT, FIELD_WIDTH, FULL_ROW,
     },
+    game::{can_hold, hold, rotate_right, Game, Position, SCORE_TABLE},
+    pieces,
 };
 
-const MAX_ERASABLE_LINE: usize = 4;
-const MAX_HEIGHT: usize = 20;
+/// 評価関数の重み
+/// 各特徴量に掛ける係数で、0にするとその特徴量は評価に使われない
+#[derive(Clone, Copy, Debug)]
+pub struct Weights {
+    pub line: f64,               // 消したライン数
+    pub score_gain: f64,         // 増えたスコア
+    pub combo: f64,              // コンボ数
+    pub height_max: f64,         // 一番高いブロックの高さ
+    pub height_diff: f64,        // 高低差の合計
+    pub dead_space: f64,         // デッドスペース(穴)の数
+    pub landing_height: f64,     // 着地したブロックの高さ
+    pub eroded_cells: f64,       // 消したライン数 × 消えたブロックのセル数
+    pub row_transitions: f64,    // 行方向の空白とブロックの切り替わり数
+    pub column_transitions: f64, // 列方向の空白とブロックの切り替わり数
+    pub buried_holes: f64,       // 穴の上に積まれたブロックの数の合計
+    pub well_depth: f64,         // 井戸の深さの累積
+    pub hole_rows: f64,          // 穴を含む行の数
+}
+
+impl Default for Weights {
+    /// Thiery & Scherrer の BCTS の重み
+    fn default() -> Self {
+        Self {
+            line: 0.0,
+            score_gain: 0.0,
+            combo: 0.0,
+            height_max: 0.0,
+            height_diff: 0.0,
+            dead_space: -13.08,
+            landing_height: -12.63,
+            eroded_cells: 6.60,
+            row_transitions: -9.22,
+            column_transitions: -19.77,
+            buried_holes: -1.61,
+            well_depth: -10.49,
+            hole_rows: -24.04,
+        }
+    }
+}
+
+/// 盤面の特徴量
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Features {
+    pub line: usize,
+    pub score_gain: usize,
+    pub combo: usize,
+    pub height_max: usize,
+    pub height_diff: usize,
+    pub dead_space: usize,
+    pub landing_height: f64,
+    pub eroded_cells: usize,
+    pub row_transitions: usize,
+    pub column_transitions: usize,
+    pub buried_holes: usize,
+    pub well_depth: usize,
+    pub hole_rows: usize,
+}
+
+impl Features {
+    /// ブロックをposに置いたときの特徴量を求める
+    /// wallsはフィールドの一番上の行の壁、comboは置く前のコンボ数
+    pub fn new(
+        board: &Bitboard,
+        walls: u16,
+        pos: &Position,
+        block: &BlockShape,
+        combo: usize,
+    ) -> Self {
+        let mask = block_mask(block);
+        let eroded_cells = eroded_cells(board, pos, &mask);
+
+        // ラインを消した後の盤面で評価する
+        let mut locked = *board;
+        place_bits(&mut locked, pos, &mask);
+        let line = erase_bits(&mut locked, walls);
+        let board = &locked;
+        Self {
+            line,
+            score_gain: SCORE_TABLE[line.min(4)],
+            combo: if line > 0 { combo + 1 } else { 0 },
+            height_max: field_height_max(board),
+            height_diff: diff_in_height(board),
+            dead_space: dead_space_count(board),
+            landing_height: landing_height(pos, block),
+            eroded_cells,
+            row_transitions: row_transitions(board),
+            column_transitions: column_transitions(board),
+            buried_holes: buried_holes(board),
+            well_depth: well_depth(board),
+            hole_rows: hole_rows(board),
+        }
+    }
 
+    /// 重み付けした特徴量の合計
+    pub fn score(&self, weights: &Weights) -> f64 {
+        self.line as f64 * weights.line
+            + self.score_gain as f64 * weights.score_gain
+            + self.combo as f64 * weights.combo
+            + self.height_max as f64 * weights.height_max
+            + self.height_diff as f64 * weights.height_diff
+            + self.dead_space as f64 * weights.dead_space
+            + self.landing_height * weights.landing_height
+            + self.eroded_cells as f64 * weights.eroded_cells
+            + self.row_transitions as f64 * weights.row_transitions
+            + self.column_transitions as f64 * weights.column_transitions
+            + self.buried_holes as f64 * weights.buried_holes
+            + self.well_depth as f64 * weights.well_depth
+            + self.hole_rows as f64 * weights.hole_rows
+    }
+}
+
+/// AIがおすすめする置き場所
+pub struct Hint {
+    pub pos: Position,     // 着地位置
+    pub block: BlockShape, // 着地するブロック
+    pub hold: bool,        // 先にホールドするか
+}
+
+/// 現在のブロックをどこに置くべきかを求める
+pub fn hint(game: &Game) -> Hint {
+    let elite = eval(game);
+    Hint {
+        pos: elite.pos,
+        block: elite.block,
+        hold: pieces::current().of(&elite.block).map(|piece| piece.name)
+            != pieces::current().of(&game.block).map(|piece| piece.name),
+    }
+}
+
+/// 標準の重みで全ての置き方を評価し、一番良い置き方をしたゲームを返す
 pub fn eval(game: &Game) -> Game {
-    let mut elite = (game.clone(), 0f64);
+    eval_with(game, &Weights::default())
+}
+
+/// 指定した重みで全ての置き方を評価し、一番良い置き方をしたゲームを返す
+/// 返すゲームはブロックを着地位置まで落とした状態で、固定はしていない
+/// 置き方の探索はビットボード上で行い、ゲームの複製はホールドと回転の組み合わせ分だけにする
+pub fn eval_with(game: &Game, weights: &Weights) -> Game {
+    let mut candidates = vec![];
+    let mut elite = (game.clone(), f64::NEG_INFINITY);
 
     // ホールド有無
     for do_hold in [true, false] {
+        if do_hold && !can_hold(game) {
+            // ルール上ホールドできない
+            continue;
+        }
         let mut game = game.clone();
         if do_hold {
             hold(&mut game);
@@ -24,78 +165,66 @@ pub fn eval(game: &Game) -> Game {
             for _ in 0..rotate_count {
                 rotate_right(&mut game);
             }
-            // 全横移動
-            for dx in -4..=5 {
-                let mut game = game.clone();
-                let new_pos = Position {
-                    x: match game.pos.x as isize + dx {
-                        (..=0) => 0,
-                        x => x as usize,
-                    },
-                    y: game.pos.y,
-                };
-                move_block(&mut game, new_pos);
-                hard_drop(&mut game);
-                fix_block(&mut game);
-
-                // インプット情報の取得
-                let line = erase_line_count(&game.field);
-                let height_max = field_height_max(&game.field);
-                let height_diff = diff_in_height(&game.field);
-                let dead_space = dead_space_count(&game.field);
-
-                // 正規化
-                let mut line = normalization(line as f64, 0.0, 4.0);
-                let mut height_max = 1.0 - normalization(height_max as f64, 0.0, 20.0);
-                let mut height_diff = 1.0 - normalization(height_diff as f64, 0.0, 200.0);
-                let mut dead_space = 1.0 - normalization(dead_space as f64, 0.0, 200.0);
-
-                // 重み付け
-                line *= 100.0;
-                height_max *= 1.0;
-                height_diff *= 10.0;
-                dead_space *= 100.0;
-
-                // インプット情報の評価
-                let score = line + height_max + height_diff + dead_space;
-                if elite.1 < score {
-                    elite = (game, score);
-                }
-            }
+            candidates.push(game);
         }
     }
 
-    elite.0
-}
+    for game in candidates {
+        let board = game.field.bits();
+        let walls = game.field.wall_row();
+        let mask = block_mask(&game.block);
+        let mut best: Option<(Position, f64)> = None;
+        // 全横移動(ブロックの左右の端の列が壁より外にはみ出さない範囲)
+        let cols = mask.iter().fold(0, |cols, &row| cols | row);
+        let left = 1usize.saturating_sub(cols.trailing_zeros() as usize);
+        let right = FIELD_WIDTH - (16 - cols.leading_zeros() as usize);
+        for x in left..right {
+            let pos = Position { x, y: game.pos.y };
+            if is_collision_bits(board, &pos, &mask) {
+                // 移動できない位置は候補にしない
+                continue;
+            }
+            let pos = drop_pos(board, &pos, &mask);
 
-/// 消去可能なラインの数を数える
-#[allow(clippy::needless_range_loop)]
-fn erase_line_count(field: &Field) -> usize {
-    let mut count = 0;
-    for y in 1..(FIELD_HEIGHT - 2) {
-        let mut can_erase = true;
-        for x in 2..(FIELD_WIDTH - 2) {
-            if field[y][x] == block_kind::NONE {
-                can_erase = false;
-                break;
+            // インプット情報の評価
+            let features = Features::new(board, walls, &pos, &game.block, game.combo);
+            let score = features.score(weights);
+            if best.is_none_or(|(_, best)| best < score) {
+                best = Some((pos, score));
             }
         }
-        if can_erase {
-            count += 1;
+        if let Some((pos, score)) = best {
+            if elite.1 < score {
+                let mut game = game;
+                game.pos = pos;
+                elite = (game, score);
+            }
         }
     }
-    count
+
+    elite.0
+}
+
+/// 壁を除いたフィールドの列
+const PLAY_COLS: u16 = FULL_ROW & !EMPTY_ROW;
+
+/// ブロックがあるかどうか
+fn is_filled(board: &Bitboard, x: usize, y: usize) -> bool {
+    board[y] & (1 << x) != 0
+}
+
+/// 床からの高さ(一番下の行が1)
+fn height_of(y: usize) -> usize {
+    FIELD_HEIGHT - 2 - y
 }
 
 /// フィールドの一番高いブロックの高さを数える
 /// ブロックが何もない場合は0を返す
 #[allow(clippy::needless_range_loop)]
-fn field_height_max(field: &Field) -> usize {
+fn field_height_max(board: &Bitboard) -> usize {
     for y in 1..(FIELD_HEIGHT - 2) {
-        for x in 2..(FIELD_WIDTH - 2) {
-            if field[y][x] != block_kind::NONE {
-                return FIELD_HEIGHT - y - 1;
-            }
+        if board[y] & PLAY_COLS != 0 {
+            return FIELD_HEIGHT - y - 1;
         }
     }
     0
@@ -103,14 +232,14 @@ fn field_height_max(field: &Field) -> usize {
 
 /// フィールドの高低差の合計を返す
 #[allow(clippy::needless_range_loop)]
-pub fn diff_in_height(field: &Field) -> usize {
+pub fn diff_in_height(board: &Bitboard) -> usize {
     let mut diff = 0;
     let mut top = [0; FIELD_WIDTH - 4];
 
     // 各列の一番上の高さを求める
     for x in 2..(FIELD_WIDTH - 2) {
         for y in 1..(FIELD_HEIGHT - 2) {
-            if field[y][x] != block_kind::NONE {
+            if is_filled(board, x, y) {
                 top[x - 2] = FIELD_HEIGHT - y - 1;
                 break;
             }
@@ -126,26 +255,273 @@ pub fn diff_in_height(field: &Field) -> usize {
 }
 
 /// デッドスペース数を返す
-pub fn dead_space_count(field: &Field) -> usize {
+#[allow(clippy::needless_range_loop)]
+pub fn dead_space_count(board: &Bitboard) -> usize {
+    let mut count = 0;
+    // 2行目からひとつ上の行までに、ブロックがある列
+    let mut covered = 0;
+    for y in 1..(FIELD_HEIGHT - 2) {
+        count += (!board[y] & covered & PLAY_COLS).count_ones() as usize;
+        if y >= 2 {
+            covered |= board[y];
+        }
+    }
+    count
+}
+
+/// 着地したブロックの高さ(ブロックの一番上と一番下の中間)を返す
+pub fn landing_height(pos: &Position, block: &BlockShape) -> f64 {
+    let rows = (0..BLOCK_SIZE)
+        .filter(|&y| block[y].iter().any(|&cell| cell != block_kind::NONE))
+        .map(|y| height_of(y + pos.y));
+    let (min, max) = rows.fold((usize::MAX, 0), |(min, max), h| (min.min(h), max.max(h)));
+    (min + max) as f64 / 2.0
+}
+
+/// 消えるラインの数と、そのラインに含まれる着地したブロックのセル数の積を返す
+/// ビットボードは着地したブロックを置く前のもの
+pub fn eroded_cells(board: &Bitboard, pos: &Position, mask: &BlockMask) -> usize {
+    let mut lines = 0;
+    let mut cells = 0;
+    for (y, &row) in mask.iter().enumerate() {
+        let field_y = y + pos.y;
+        if row == 0 || field_y >= FIELD_HEIGHT - 2 {
+            continue;
+        }
+        if (board[field_y] | row << pos.x) & FULL_ROW == FULL_ROW {
+            lines += 1;
+            cells += row.count_ones() as usize;
+        }
+    }
+    lines * cells
+}
+
+/// 行方向に空白とブロックが切り替わる回数を返す
+/// 左右の壁はブロックとして扱う
+pub fn row_transitions(board: &Bitboard) -> usize {
+    // 左の壁から、右の壁の1つ左の列まで
+    const COLS: u16 = PLAY_COLS | 1 << 1;
+    board[..(FIELD_HEIGHT - 2)]
+        .iter()
+        .map(|&row| ((row ^ row >> 1) & COLS).count_ones() as usize)
+        .sum()
+}
+
+/// 列方向に空白とブロックが切り替わる回数を返す
+/// 底はブロックとして扱う
+pub fn column_transitions(board: &Bitboard) -> usize {
+    board[..(FIELD_HEIGHT - 1)]
+        .windows(2)
+        .map(|rows| ((rows[0] ^ rows[1]) & PLAY_COLS).count_ones() as usize)
+        .sum()
+}
+
+/// 穴ごとに、その上に積まれているブロックの数を合計して返す
+pub fn buried_holes(board: &Bitboard) -> usize {
     let mut count = 0;
-    for y in (1..(FIELD_HEIGHT - 2)).rev() {
-        for x in 2..(FIELD_WIDTH - 2) {
-            // 各列の一番下の何もない座標
-            if field[y][x] == block_kind::NONE {
-                for y2 in (2..y).rev() {
-                    // 上にブロックがあるならデッドスペース
-                    if field[y2][x] != block_kind::NONE {
-                        count += 1;
-                        break;
-                    }
-                }
+    // above[k]: 上にk+1個以上のブロックがある列
+    let mut above = [0u16; FIELD_HEIGHT];
+    let mut len = 0;
+    for &row in &board[..(FIELD_HEIGHT - 2)] {
+        let holes = !row & PLAY_COLS;
+        count += above[..len]
+            .iter()
+            .map(|&above| (above & holes).count_ones() as usize)
+            .sum::<usize>();
+        if row & PLAY_COLS != 0 {
+            for k in (1..=len).rev() {
+                above[k] |= above[k - 1] & row;
             }
+            above[0] |= row & PLAY_COLS;
+            len += 1;
+        }
+    }
+    count
+}
+
+/// 井戸(左右がブロックで埋まっている空白)の深さの累積を返す
+/// 深さ3の井戸は 1 + 2 + 3 = 6 として数える
+pub fn well_depth(board: &Bitboard) -> usize {
+    let mut sum = 0;
+    // depth[k]: 深さk+1以上の井戸が続いている列
+    let mut depth = [0u16; FIELD_HEIGHT];
+    let mut len = 0;
+    for &row in &board[..(FIELD_HEIGHT - 2)] {
+        let wells = !row & row << 1 & row >> 1 & PLAY_COLS;
+        for k in (1..=len).rev() {
+            depth[k] = depth[k - 1] & wells;
+        }
+        depth[0] = wells;
+        len = (len + 1).min(FIELD_HEIGHT - 1);
+        while len > 0 && depth[len - 1] == 0 {
+            len -= 1;
         }
+        sum += depth[..len]
+            .iter()
+            .map(|&depth| depth.count_ones() as usize)
+            .sum::<usize>();
+    }
+    sum
+}
+
+/// 穴を1つ以上含む行の数を返す
+pub fn hole_rows(board: &Bitboard) -> usize {
+    let mut covered = 0;
+    let mut count = 0;
+    for &row in &board[..(FIELD_HEIGHT - 2)] {
+        if !row & covered & PLAY_COLS != 0 {
+            count += 1;
+        }
+        covered |= row;
     }
     count
 }
 
-// 正規化 (Min-Max Normalization)
-fn normalization(value: f64, min: f64, max: f64) -> f64 {
-    (value - min) / (max - min)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::BlockKind, board::Board, field::EMPTY_FIELD};
+
+    /// 盤面のテキスト形式のフィールドをビットボードにする
+    fn bits(text: &str) -> Bitboard {
+        *Board::parse(text).unwrap().field.bits()
+    }
+
+    /// 列2と列10の一番下に穴があり、列1が深さ1の井戸になっている盤面
+    const HOLES: &str = "\
+#..........
+#.#.......#
+##.#######.
+";
+
+    #[test]
+    fn board_features() {
+        let board = bits(HOLES);
+        // 高さは底の行も1段として数える
+        assert_eq!(field_height_max(&board), 4);
+        // 各列の高さは 3,1,2,1,1,1,1,1,1,1,2
+        assert_eq!(diff_in_height(&board), 5);
+        assert_eq!(dead_space_count(&board), 2);
+        // 空の17行は左右の壁の2回ずつ
+        assert_eq!(row_transitions(&board), 17 * 2 + 2 + 4 + 4);
+        assert_eq!(column_transitions(&board), 1 + 1 + 3 + 7 + 3);
+        assert_eq!(buried_holes(&board), 2);
+        // 列1(y=18)、列2と列10(y=19)がそれぞれ深さ1
+        assert_eq!(well_depth(&board), 3);
+        assert_eq!(hole_rows(&board), 1);
+    }
+
+    #[test]
+    fn deep_well_and_buried_hole() {
+        let board = bits(
+            "\
+#.#.......#
+#.#.......#
+#.########.
+",
+        );
+        // 列1は深さ3の井戸(1 + 2 + 3)、列10は一番下だけが井戸
+        assert_eq!(well_depth(&board), 6 + 1);
+        // 列10の穴の上に2つ
+        assert_eq!(buried_holes(&board), 2);
+        assert_eq!(hole_rows(&board), 1);
+        assert_eq!(dead_space_count(&board), 1);
+    }
+
+    #[test]
+    fn landing_height_and_eroded_cells() {
+        // 横向きのIブロックで一番下の行を消す
+        let board = bits("#######....\n");
+        let block = BlockKind::I.shape();
+        let pos = Position {
+            x: 9,
+            y: FIELD_HEIGHT - 5,
+        };
+        assert_eq!(landing_height(&pos, &block), 1.0);
+        assert_eq!(eroded_cells(&board, &pos, &block_mask(&block)), 4);
+
+        // 縦向きのIブロックで4行消す
+        let board = bits(&"##########.\n".repeat(4));
+        let mut block = BlockShape::default();
+        for row in &mut block[..4] {
+            row[0] = block_kind::I;
+        }
+        let pos = Position {
+            x: FIELD_WIDTH - 3,
+            y: FIELD_HEIGHT - 6,
+        };
+        assert_eq!(landing_height(&pos, &block), 2.5);
+        assert_eq!(eroded_cells(&board, &pos, &block_mask(&block)), 4 * 4);
+    }
+
+    #[test]
+    fn clearing_move_is_scored_after_the_clear() {
+        // 消える行の下にある穴は、消した後の盤面では穴でなくなる
+        let board = bits("#######....\n#.#########\n");
+        let walls = EMPTY_FIELD.wall_row();
+        let block = BlockKind::I.shape();
+        let pos = Position {
+            x: 9,
+            y: FIELD_HEIGHT - 6,
+        };
+        let cleared = Features::new(&board, walls, &pos, &block, 0);
+        assert_eq!(cleared.line, 1);
+        assert_eq!(cleared.score_gain, SCORE_TABLE[1]);
+        assert_eq!(cleared.combo, 1);
+        assert_eq!(cleared.column_transitions, 11);
+        assert_eq!((cleared.buried_holes, cleared.hole_rows), (0, 0));
+
+        // 同じ手を、揃った行が残ったままの盤面で評価する
+        let mut locked = board;
+        place_bits(&mut locked, &pos, &block_mask(&block));
+        let uncleared = Features {
+            height_max: field_height_max(&locked),
+            height_diff: diff_in_height(&locked),
+            dead_space: dead_space_count(&locked),
+            row_transitions: row_transitions(&locked),
+            column_transitions: column_transitions(&locked),
+            buried_holes: buried_holes(&locked),
+            well_depth: well_depth(&locked),
+            hole_rows: hole_rows(&locked),
+            ..cleared
+        };
+        assert_eq!(uncleared.column_transitions, 13);
+        assert_eq!((uncleared.buried_holes, uncleared.hole_rows), (1, 1));
+        let weights = Weights::default();
+        assert!(cleared.score(&weights) > uncleared.score(&weights));
+    }
+
+    #[test]
+    fn bcts_weights() {
+        // HOLESの列5と列6にOブロックを置く(ラインは消えない)
+        let board = bits(HOLES);
+        let pos = Position {
+            x: 6,
+            y: FIELD_HEIGHT - 6,
+        };
+        let features = Features::new(
+            &board,
+            EMPTY_FIELD.wall_row(),
+            &pos,
+            &BlockKind::O.shape(),
+            0,
+        );
+        assert_eq!(features.line, 0);
+        assert_eq!(features.landing_height, 2.5);
+        assert_eq!(features.eroded_cells, 0);
+        assert_eq!(features.dead_space, 2);
+        assert_eq!(features.row_transitions, 17 * 2 + 4 + 6 + 4);
+        assert_eq!(features.column_transitions, 15);
+        assert_eq!(features.buried_holes, 2);
+        assert_eq!(features.well_depth, 3);
+        assert_eq!(features.hole_rows, 1);
+        let expected = -13.08 * 2.0
+            - 12.63 * 2.5
+            - 9.22 * 48.0
+            - 19.77 * 15.0
+            - 1.61 * 2.0
+            - 10.49 * 3.0
+            - 24.04 * 1.0;
+        assert!((features.score(&Weights::default()) - expected).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,126 @@
+//! アリカ式の回転法則
+//!
+//! ブロックの向きごとの形を表で持ち、回転できなければ右、左の順に1マスずらして入れる
+//! Iブロックはずらさず、J, L, Tブロックは中央の列に当たった場合はずらさない
+
+use crate::{
+    block::{block_kind, BlockKind, BlockShape},
+    game::{is_collision, Game, Position},
+};
+
+/// ブロックの向きごとの形(BlockKindの順、`#` がセル)
+/// 3x3に収まるブロックは横向きのとき下の2行に置く
+const STATES: [[[&str; 4]; 4]; 7] = [
+    // I
+    [
+        ["....", "####", "....", "...."],
+        ["..#.", "..#.", "..#.", "..#."],
+        ["....", "####", "....", "...."],
+        ["..#.", "..#.", "..#.", "..#."],
+    ],
+    // O
+    [
+        ["....", ".##.", ".##.", "...."],
+        ["....", ".##.", ".##.", "...."],
+        ["....", ".##.", ".##.", "...."],
+        ["....", ".##.", ".##.", "...."],
+    ],
+    // S
+    [
+        ["....", ".##.", "##..", "...."],
+        ["#...", "##..", ".#..", "...."],
+        ["....", ".##.", "##..", "...."],
+        ["#...", "##..", ".#..", "...."],
+    ],
+    // Z
+    [
+        ["....", "##..", ".##.", "...."],
+        ["..#.", ".##.", ".#..", "...."],
+        ["....", "##..", ".##.", "...."],
+        ["..#.", ".##.", ".#..", "...."],
+    ],
+    // J
+    [
+        ["....", "###.", "..#.", "...."],
+        [".#..", ".#..", "##..", "...."],
+        ["....", "#...", "###.", "...."],
+        [".##.", ".#..", ".#..", "...."],
+    ],
+    // L
+    [
+        ["....", "###.", "#...", "...."],
+        ["##..", ".#..", ".#..", "...."],
+        ["....", "..#.", "###.", "...."],
+        [".#..", ".#..", ".##.", "...."],
+    ],
+    // T
+    [
+        ["....", "###.", ".#..", "...."],
+        [".#..", "##..", ".#..", "...."],
+        ["....", ".#..", "###.", "...."],
+        [".#..", ".##.", ".#..", "...."],
+    ],
+];
+
+/// 向きを指定したブロックの形(0が出てきたときの向きで、1つ増えるごとに右に90度回転)
+pub fn shape(kind: BlockKind, state: usize) -> BlockShape {
+    let mut shape = BlockShape::default();
+    for (y, row) in STATES[kind as usize][state % 4].iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            if c == '#' {
+                shape[y][x] = kind.color();
+            }
+        }
+    }
+    shape
+}
+
+/// 今の形の向き(表にない形なら出てきたときの向きとみなす)
+fn state_of(kind: BlockKind, block: &BlockShape) -> usize {
+    (0..4)
+        .find(|&state| shape(kind, state) == *block)
+        .unwrap_or(0)
+}
+
+/// 回転する(`clockwise` がtrueなら右回転)
+pub fn rotate(game: &mut Game, clockwise: bool) {
+    let Some(kind) = BlockKind::from_shape(&game.block) else {
+        return;
+    };
+    let state = state_of(kind, &game.block) + if clockwise { 1 } else { 3 };
+    let new_shape = shape(kind, state);
+    if let Some(new_pos) = kick(game, kind, &new_shape) {
+        game.pos = new_pos;
+        game.block = new_shape;
+        game.rotated = true;
+    }
+}
+
+/// 回転した形を入れられる位置を探す
+fn kick(game: &Game, kind: BlockKind, block: &BlockShape) -> Option<Position> {
+    let pos = game.pos;
+    if !is_collision(&game.field, &pos, block) {
+        return Some(pos);
+    }
+    if kind == BlockKind::I {
+        return None;
+    }
+    if matches!(kind, BlockKind::J | BlockKind::L | BlockKind::T) {
+        // 最初に当たったセルが中央の列ならずらさない
+        let colors = game.field.colors();
+        let first = (0..3)
+            .flat_map(|y| (0..3).map(move |x| (x, y)))
+            .find(|&(x, y)| {
+                block[y][x] != block_kind::NONE && colors[y + pos.y][x + pos.x] != block_kind::NONE
+            });
+        if let Some((1, _)) = first {
+            return None;
+        }
+    }
+    // 右、左の順にずらす
+    [Some(pos.x + 1), pos.x.checked_sub(1)]
+        .into_iter()
+        .flatten()
+        .map(|x| Position { x, y: pos.y })
+        .find(|pos| !is_collision(&game.field, pos, block))
+}
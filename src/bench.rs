@@ -0,0 +1,196 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    ai::eval,
+    board::Board,
+    event::Event,
+    game::{landing, Game},
+    rules::Rules,
+};
+
+/// 1ゲーム分の結果
+#[derive(Debug, Clone, Serialize)]
+pub struct GameResult {
+    pub seed: u64,
+    pub lines: usize,
+    pub pieces: usize,
+    pub score: usize,
+    pub tetrises: usize,
+    pub tetris_rate: f64,    // 4段消しで消したライン数の割合
+    pub search_time_us: f64, // 1ブロックあたりの平均探索時間(マイクロ秒)
+    pub topped_out: bool,
+}
+
+/// 統計値
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    fn new(values: &[f64]) -> Self {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let len = sorted.len();
+        if len == 0 {
+            return Self {
+                mean: 0.0,
+                median: 0.0,
+                min: 0.0,
+                max: 0.0,
+            };
+        }
+        let median = if len.is_multiple_of(2) {
+            (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
+        } else {
+            sorted[len / 2]
+        };
+        Self {
+            mean: sorted.iter().sum::<f64>() / len as f64,
+            median,
+            min: sorted[0],
+            max: sorted[len - 1],
+        }
+    }
+}
+
+/// ベンチマーク全体の結果
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub max_pieces: usize,
+    pub games: Vec<GameResult>,
+    pub lines: Summary,
+    pub pieces: Summary,
+    pub score: Summary,
+    pub tetris_rate: Summary,
+    pub search_time_us: Summary,
+}
+
+impl Report {
+    fn new(max_pieces: usize, games: Vec<GameResult>) -> Self {
+        let summary =
+            |f: fn(&GameResult) -> f64| Summary::new(&games.iter().map(f).collect::<Vec<_>>());
+        Self {
+            max_pieces,
+            lines: summary(|game| game.lines as f64),
+            pieces: summary(|game| game.pieces as f64),
+            score: summary(|game| game.score as f64),
+            tetris_rate: summary(|game| game.tetris_rate),
+            search_time_us: summary(|game| game.search_time_us),
+            games,
+        }
+    }
+}
+
+/// 表形式で出力する
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>20} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            "seed", "lines", "pieces", "score", "tetris%", "us/piece"
+        )?;
+        for game in &self.games {
+            writeln!(
+                f,
+                "{:>20} {:>8} {:>8} {:>8} {:>8.1} {:>8.1}{}",
+                game.seed,
+                game.lines,
+                game.pieces,
+                game.score,
+                game.tetris_rate * 100.0,
+                game.search_time_us,
+                if game.topped_out { " (top out)" } else { "" }
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>20} {:>12} {:>12} {:>12} {:>12}",
+            "", "mean", "median", "min", "max"
+        )?;
+        for (name, summary, scale) in [
+            ("lines", &self.lines, 1.0),
+            ("pieces", &self.pieces, 1.0),
+            ("score", &self.score, 1.0),
+            ("tetris%", &self.tetris_rate, 100.0),
+            ("us/piece", &self.search_time_us, 1.0),
+        ] {
+            writeln!(
+                f,
+                "{:>20} {:>12.1} {:>12.1} {:>12.1} {:>12.1}",
+                name,
+                summary.mean * scale,
+                summary.median * scale,
+                summary.min * scale,
+                summary.max * scale
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 画面に描画せずに1ゲームをAIにプレイさせる
+/// 盤面を指定した場合はその盤面から始める
+pub fn run_game(seed: u64, max_pieces: usize, board: Option<&Board>, rules: Rules) -> GameResult {
+    let mut game = Game::with_rules(seed, rules);
+    if let Some(board) = board {
+        board.apply(&mut game);
+    }
+    let mut pieces = 0;
+    let mut tetrises = 0;
+    let mut search_time = Duration::ZERO;
+    let mut topped_out = false;
+
+    while pieces < max_pieces {
+        let start = Instant::now();
+        let elite = eval(&game);
+        search_time += start.elapsed();
+        game = elite;
+
+        let result = landing(&mut game);
+        pieces += 1;
+        tetrises += game
+            .drain_events()
+            .filter(|event| matches!(event, Event::LinesCleared { count: 4, .. }))
+            .count();
+        if result.is_err() {
+            topped_out = true;
+            break;
+        }
+    }
+
+    GameResult {
+        seed,
+        lines: game.line,
+        pieces,
+        score: game.score,
+        tetrises,
+        tetris_rate: match game.line {
+            0 => 0.0,
+            line => (tetrises * 4) as f64 / line as f64,
+        },
+        search_time_us: match pieces {
+            0 => 0.0,
+            pieces => search_time.as_secs_f64() * 1_000_000.0 / pieces as f64,
+        },
+        topped_out,
+    }
+}
+
+/// 指定したシード値のゲームを全て実行する
+pub fn run(seeds: &[u64], max_pieces: usize, board: Option<&Board>, rules: Rules) -> Report {
+    let games = seeds
+        .iter()
+        .map(|&seed| run_game(seed, max_pieces, board, rules))
+        .collect();
+    Report::new(max_pieces, games)
+}
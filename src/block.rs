@@ -1,10 +1,16 @@
 use block_kind::{I, J, L, O, S, T, Z};
+use std::collections::VecDeque;
+
 use rand::{
     distributions::{Distribution, Standard},
     seq::SliceRandom,
-    thread_rng, Rng,
+    Rng,
 };
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::pieces;
 
+/// セルの色(block_kindの値)
 pub type BlockColor = usize;
 const BLOCK_KIND_MAX: usize = 7;
 pub mod block_kind {
@@ -18,22 +24,13 @@ pub mod block_kind {
     pub const J: super::BlockColor = 7;
     pub const L: super::BlockColor = 8;
     pub const T: super::BlockColor = 9;
+    pub const GARBAGE: super::BlockColor = 10;
+    pub const HINT: super::BlockColor = 11;
+    /// ピースセットで定義したブロックの色(ここから順に割り当てる)
+    pub const PIECE: super::BlockColor = 12;
 }
 
-pub const COLOR_TABLE: [&str; 10] = [
-    "\x1b[48;2;000;000;000m  ", // 何もなし
-    "\x1b[48;2;127;127;127m__", // 壁
-    "\x1b[48;2;000;000;000m[]", // ゴースト
-    "\x1b[48;2;000;000;255m__", // I
-    "\x1b[48;2;000;255;000m__", // O
-    "\x1b[48;2;000;255;255m__", // S
-    "\x1b[48;2;255;000;000m__", // Z
-    "\x1b[48;2;255;000;255m__", // J
-    "\x1b[48;2;255;127;000m__", // L
-    "\x1b[48;2;255;255;000m__", // T
-];
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BlockKind {
     I,
     O,
@@ -44,72 +41,171 @@ pub enum BlockKind {
     T,
 }
 
-pub type BlockShape = [[usize; 4]; 4];
+impl BlockKind {
+    pub const ALL: [BlockKind; BLOCK_KIND_MAX] = [
+        BlockKind::I,
+        BlockKind::O,
+        BlockKind::S,
+        BlockKind::Z,
+        BlockKind::J,
+        BlockKind::L,
+        BlockKind::T,
+    ];
+
+    /// ブロックの色
+    pub fn color(self) -> BlockColor {
+        self as BlockColor + block_kind::I
+    }
+
+    /// 色からブロックの種類を求める
+    pub fn from_color(color: BlockColor) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.color() == color)
+    }
+
+    /// ブロックの形から種類を求める
+    pub fn from_shape(shape: &BlockShape) -> Option<Self> {
+        shape
+            .iter()
+            .flatten()
+            .find(|&&cell| cell != block_kind::NONE)
+            .and_then(|&cell| Self::from_color(cell))
+    }
+
+    /// ブロックを表す文字
+    pub fn to_char(self) -> char {
+        match self {
+            BlockKind::I => 'I',
+            BlockKind::O => 'O',
+            BlockKind::S => 'S',
+            BlockKind::Z => 'Z',
+            BlockKind::J => 'J',
+            BlockKind::L => 'L',
+            BlockKind::T => 'T',
+        }
+    }
+
+    /// 文字からブロックの種類を求める
+    pub fn from_char(c: char) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_char() == c.to_ascii_uppercase())
+    }
+
+    /// 初期状態のブロックの形
+    pub fn shape(self) -> BlockShape {
+        BLOCKS[self as usize]
+    }
+}
+
+/// ブロックの形を入れる正方形の大きさ
+pub const BLOCK_SIZE: usize = 5;
+/// ブロックの形(セルの色)
+/// 回転するときはピースセットで決めた大きさの正方形を左上から使う
+pub type BlockShape = [[usize; BLOCK_SIZE]; BLOCK_SIZE];
+/// 出てきたときの向きのブロックの形(BlockKindの順)
 pub const BLOCKS: [BlockShape; BLOCK_KIND_MAX] = [
     // I
     [
-        [0, 0, 0, 0],
-        [0, 0, 0, 0],
-        [I, I, I, I],
-        [0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [I, I, I, I, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
     ],
     // O
     [
-        [0, 0, 0, 0],
-        [0, O, O, 0],
-        [0, O, O, 0],
-        [0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, O, O, 0, 0],
+        [0, O, O, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
     ],
     // S
     [
-        [0, 0, 0, 0],
-        [0, S, S, 0],
-        [S, S, 0, 0],
-        [0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, S, S, 0, 0],
+        [S, S, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
     ],
     // Z
     [
-        [0, 0, 0, 0],
-        [Z, Z, 0, 0],
-        [0, Z, Z, 0],
-        [0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [Z, Z, 0, 0, 0],
+        [0, Z, Z, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
     ],
     // J
     [
-        [0, 0, 0, 0],
-        [J, 0, 0, 0],
-        [J, J, J, 0],
-        [0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [J, 0, 0, 0, 0],
+        [J, J, J, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
     ],
     // L
     [
-        [0, 0, 0, 0],
-        [0, 0, L, 0],
-        [L, L, L, 0],
-        [0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, L, 0, 0],
+        [L, L, L, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
     ],
     // T
     [
-        [0, 0, 0, 0],
-        [0, T, 0, 0],
-        [T, T, T, 0],
-        [0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, T, 0, 0, 0],
+        [T, T, T, 0, 0],
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0],
     ],
 ];
 
-pub fn gen_block_7() -> [BlockShape; BLOCK_KIND_MAX] {
-    let mut rng = thread_rng();
-    let mut que = [
-        BlockKind::I,
-        BlockKind::O,
-        BlockKind::S,
-        BlockKind::Z,
-        BlockKind::J,
-        BlockKind::L,
-        BlockKind::T,
-    ];
-    que.shuffle(&mut rng);
-    que.map(|block| BLOCKS[block as usize])
+/// ピースセットのブロックを1つずつ並べ替えた順番を作る(7種1巡)
+pub fn gen_bag<R: Rng + ?Sized>(rng: &mut R) -> Vec<BlockShape> {
+    let mut que: Vec<BlockShape> = pieces::current()
+        .pieces
+        .iter()
+        .map(|piece| piece.shape)
+        .collect();
+    que.shuffle(rng);
+    que
+}
+
+/// 保存したブロックの形を読み込む(4x4で保存した形も読める)
+pub mod shape_serde {
+    use super::*;
+
+    fn to_shape<E: serde::de::Error>(rows: Vec<Vec<BlockColor>>) -> Result<BlockShape, E> {
+        if rows.len() > BLOCK_SIZE || rows.iter().any(|row| row.len() > BLOCK_SIZE) {
+            return Err(E::custom("block is too large"));
+        }
+        let mut shape = BlockShape::default();
+        for (y, row) in rows.into_iter().enumerate() {
+            shape[y][..row.len()].copy_from_slice(&row);
+        }
+        Ok(shape)
+    }
+
+    pub fn shape<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BlockShape, D::Error> {
+        to_shape(Vec::deserialize(deserializer)?)
+    }
+
+    pub fn option<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<BlockShape>, D::Error> {
+        Option::deserialize(deserializer)?.map(to_shape).transpose()
+    }
+
+    pub fn queue<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<VecDeque<BlockShape>, D::Error> {
+        Vec::deserialize(deserializer)?
+            .into_iter()
+            .map(to_shape)
+            .collect()
+    }
 }
 
 impl Distribution<BlockKind> for Standard {
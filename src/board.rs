@@ -0,0 +1,269 @@
+//! 盤面のテキスト形式
+//!
+//! ```text
+//! hold: T
+//! current: I
+//! queue: OSZ
+//! ...........
+//! ##SS.######
+//! #SS..######
+//! ```
+//!
+//! - `hold`, `current`, `queue` は省略できる
+//! - フィールドは1行11文字で、`.` が空白、`#` がお邪魔ブロック、`IOSZJLT` がそれぞれのブロック
+//! - 1行10文字の場合は一番右の列を壁にする(ガイドラインやテト譜と同じ幅)
+//! - フィールドの行は下詰めで、20行より少ない場合は上が空白になる
+//! - `;` で始まる行はコメント
+
+use std::{fs, path::Path};
+
+use crate::{
+    block::{block_kind, BlockKind},
+    field::{narrow_field, Field, EMPTY_FIELD, FIELD_HEIGHT, FIELD_WIDTH, GUIDELINE_WIDTH},
+    game::{fill_next, spawn_pos, spawn_shape, Game},
+};
+
+/// フィールドの幅(壁を除く)
+const WIDTH: usize = FIELD_WIDTH - 4;
+/// フィールドの高さ(底を除く)
+const HEIGHT: usize = FIELD_HEIGHT - 2;
+
+/// 盤面
+#[derive(Clone)]
+pub struct Board {
+    pub field: Field,
+    pub current: Option<BlockKind>,
+    pub hold: Option<BlockKind>,
+    pub queue: Vec<BlockKind>,
+}
+
+impl Board {
+    /// ファイルから読み込む
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// テキスト形式から盤面を読み込む
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut current = None;
+        let mut hold = None;
+        let mut queue = vec![];
+        let mut rows: Vec<Vec<_>> = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let pieces = parse_pieces(value).map_err(|err| format!("line {}: {err}", i + 1))?;
+                match key.trim() {
+                    "current" => current = pieces.first().copied(),
+                    "hold" => hold = pieces.first().copied(),
+                    "queue" => queue = pieces,
+                    key => return Err(format!("line {}: unknown key `{key}`", i + 1)),
+                }
+                continue;
+            }
+            let width = line.chars().count();
+            if width != WIDTH && width != GUIDELINE_WIDTH {
+                return Err(format!(
+                    "line {}: row must be {WIDTH} or {GUIDELINE_WIDTH} cells wide",
+                    i + 1
+                ));
+            }
+            if rows.first().is_some_and(|row| row.len() != width) {
+                return Err(format!("line {}: rows must have the same width", i + 1));
+            }
+            let row = line
+                .chars()
+                .map(|c| match c {
+                    '.' => Some(block_kind::NONE),
+                    '#' => Some(block_kind::GARBAGE),
+                    c => BlockKind::from_char(c).map(BlockKind::color),
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| format!("line {}: invalid cell", i + 1))?;
+            rows.push(row);
+        }
+
+        if rows.len() > HEIGHT {
+            return Err(format!("field must be at most {HEIGHT} rows high"));
+        }
+
+        // 下詰めでフィールドに書き込む
+        let mut field = EMPTY_FIELD;
+        let top = HEIGHT - rows.len();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &color) in row.iter().enumerate() {
+                field.set(x + 2, y + top, color);
+            }
+        }
+        if rows.first().is_some_and(|row| row.len() == GUIDELINE_WIDTH) {
+            narrow_field(&mut field);
+        }
+
+        Ok(Self {
+            field,
+            current,
+            hold,
+            queue,
+        })
+    }
+
+    /// ゲームの現在の盤面
+    pub fn from_game(game: &Game) -> Self {
+        Self {
+            field: game.field,
+            current: BlockKind::from_shape(&game.block),
+            hold: game.hold.as_ref().and_then(BlockKind::from_shape),
+            queue: game.next.iter().filter_map(BlockKind::from_shape).collect(),
+        }
+    }
+
+    /// テキスト形式に変換する
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(hold) = self.hold {
+            text += &format!("hold: {}\n", hold.to_char());
+        }
+        if let Some(current) = self.current {
+            text += &format!("current: {}\n", current.to_char());
+        }
+        if !self.queue.is_empty() {
+            let queue: String = self.queue.iter().map(|kind| kind.to_char()).collect();
+            text += &format!("queue: {queue}\n");
+        }
+
+        // 一番右の列が壁なら1行10文字で書き、一番上の空白の行は省略する
+        // 幅が分かるように、10文字の場合は一番下の行を必ず書く
+        let narrow = (0..HEIGHT).all(|y| self.field[y][FIELD_WIDTH - 3] == block_kind::WALL);
+        let right = if narrow {
+            FIELD_WIDTH - 3
+        } else {
+            FIELD_WIDTH - 2
+        };
+        let rows = (0..HEIGHT).skip_while(|&y| {
+            (!narrow || y + 1 < HEIGHT) && (2..right).all(|x| self.field[y][x] == block_kind::NONE)
+        });
+        for y in rows {
+            for x in 2..right {
+                text.push(match self.field[y][x] {
+                    block_kind::NONE => '.',
+                    color => BlockKind::from_color(color).map_or('#', BlockKind::to_char),
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// ゲームに盤面を反映する
+    /// 省略されたブロックはそのまま、ネクストが足りない場合は補充する
+    pub fn apply(&self, game: &mut Game) {
+        game.field = self.field;
+        if let Some(hold) = self.hold {
+            game.hold = Some(hold.shape());
+        }
+        game.holded = false;
+        if let Some(current) = self.current {
+            game.block = spawn_shape(&game.rules, &current.shape());
+            game.pos = spawn_pos(&game.block);
+        }
+        if !self.queue.is_empty() {
+            game.next = self.queue.iter().map(|kind| kind.shape()).collect();
+            fill_next(game);
+        }
+    }
+}
+
+/// `IOSZ` のようなブロックの並びを読み込む
+fn parse_pieces(text: &str) -> Result<Vec<BlockKind>, String> {
+    text.chars()
+        .filter(|c| !c.is_whitespace() && *c != ',')
+        .map(|c| BlockKind::from_char(c).ok_or_else(|| format!("invalid piece `{c}`")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_wide_board() {
+        let board = Board::parse(
+            "; コメント
+hold: T
+current: I
+queue: O, S, Z
+...........
+##SS.######
+",
+        )
+        .unwrap();
+        assert_eq!(board.hold, Some(BlockKind::T));
+        assert_eq!(board.current, Some(BlockKind::I));
+        assert_eq!(board.queue, [BlockKind::O, BlockKind::S, BlockKind::Z]);
+        assert_eq!(board.field[HEIGHT - 1][2], block_kind::GARBAGE);
+        assert_eq!(board.field[HEIGHT - 1][4], BlockKind::S.color());
+        assert_eq!(board.field[HEIGHT - 1][6], block_kind::NONE);
+        assert_eq!(board.field[HEIGHT - 1][12], block_kind::GARBAGE);
+        assert_eq!(board.field[0][12], block_kind::NONE);
+        assert_eq!(
+            Board::parse(&board.to_text()).unwrap().to_text(),
+            board.to_text()
+        );
+    }
+
+    #[test]
+    fn parse_narrow_board() {
+        let board = Board::parse("####.#####\n").unwrap();
+        assert_eq!(board.current, None);
+        assert!(board.queue.is_empty());
+        assert_eq!(board.field[HEIGHT - 1][6], block_kind::NONE);
+        assert_eq!(board.field[HEIGHT - 1][11], block_kind::GARBAGE);
+        assert!((0..HEIGHT).all(|y| board.field[y][12] == block_kind::WALL));
+        assert_eq!(board.to_text(), "####.#####\n");
+    }
+
+    #[test]
+    fn parse_malformed_board() {
+        for (text, err) in [
+            ("....\n", "line 1: row must be 11 or 10 cells wide"),
+            (
+                "...........\n..........\n",
+                "line 2: rows must have the same width",
+            ),
+            ("....X.....\n", "line 1: invalid cell"),
+            ("next: I\n", "line 1: unknown key `next`"),
+            ("queue: IX\n", "line 1: invalid piece `X`"),
+            (
+                &"..........\n".repeat(HEIGHT + 1),
+                "field must be at most 20 rows high",
+            ),
+        ] {
+            assert_eq!(Board::parse(text).err().as_deref(), Some(err), "{text}");
+        }
+    }
+
+    #[test]
+    fn apply_keeps_omitted_hold() {
+        let mut game = Game::new();
+        game.hold = Some(BlockKind::O.shape());
+        Board::parse("current: T\n").unwrap().apply(&mut game);
+        assert_eq!(game.hold, Some(BlockKind::O.shape()));
+        Board::parse("hold: I\n").unwrap().apply(&mut game);
+        assert_eq!(game.hold, Some(BlockKind::I.shape()));
+    }
+
+    #[test]
+    fn apply_spawns_current_piece() {
+        let board = Board::parse("current: T\n").unwrap();
+        let mut game = Game::new();
+        game.pos.y += 5;
+        board.apply(&mut game);
+        assert_eq!(game.block, BlockKind::T.shape());
+        assert_eq!(game.pos, spawn_pos(&game.block));
+    }
+}
@@ -0,0 +1,56 @@
+//! ゲーム中に起きたことの通知
+//!
+//! 操作の結果起きたことはゲームのイベントキューに溜まり、
+//! 描画や統計、効果音などはそれを取り出して使う
+//!
+//! ```
+//! use rust_tetris_tutorial::{event::Event, game::{self, Game}};
+//!
+//! let mut game = Game::with_seed(0);
+//! game::hard_drop(&mut game);
+//! game::landing(&mut game).unwrap();
+//! for event in game.drain_events() {
+//!     if let Event::LinesCleared { count, .. } = event {
+//!         println!("{count} lines");
+//!     }
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::BlockKind,
+    game::{GameOverReason, Position},
+};
+
+/// 取り出されずに溜まっておけるイベントの数(超えたら古いものから捨てる)
+pub const MAX_EVENTS: usize = 1024;
+
+/// ゲーム中に起きたこと
+/// ブロックの種類は7種類以外の形の場合は `None`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    /// ブロックが出てきた
+    PieceSpawned { kind: Option<BlockKind> },
+    /// ブロックを固定した
+    PieceLocked {
+        kind: Option<BlockKind>,
+        pos: Position,
+    },
+    /// ラインを消した
+    LinesCleared {
+        count: usize,        // 消したライン数
+        spin: bool,          // Tスピンか
+        b2b: bool,           // Back-to-Backか
+        combo: usize,        // 連続でラインを消した回数
+        perfect_clear: bool, // パーフェクトクリアか
+    },
+    /// ホールドした
+    HoldUsed { kind: Option<BlockKind> },
+    /// レベルが上がった
+    LevelUp { level: usize },
+    /// お邪魔ブロックがせり上がった
+    GarbageReceived { lines: usize },
+    /// ゲームオーバーになった
+    GameOver { reason: GameOverReason },
+}
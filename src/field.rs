@@ -0,0 +1,301 @@
+use std::ops::Index;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::{block_kind, BlockColor, BlockShape, BLOCK_SIZE},
+    game::Position,
+};
+
+pub const FIELD_WIDTH: usize = 11 + 2 + 2; //  フィールド + 壁 + 番兵
+pub const FIELD_HEIGHT: usize = 20 + 1 + 1; // フィールド + 底 + 番兵
+
+/// 各セルの色(描画用)
+pub type Colors = [[BlockColor; FIELD_WIDTH]; FIELD_HEIGHT];
+/// 各行のブロックの有無をビットで表したもの(x列目がxビット目)
+pub type Bitboard = [u16; FIELD_HEIGHT];
+/// ブロックの各行のビット(位置は0列目から)
+pub type BlockMask = [u16; BLOCK_SIZE];
+
+/// ガイドラインのフィールドの幅
+pub const GUIDELINE_WIDTH: usize = 10;
+
+/// 壁だけがある行
+pub const EMPTY_ROW: u16 = 1 << 1 | 1 << (FIELD_WIDTH - 2);
+/// 壁とブロックで埋まった行
+pub const FULL_ROW: u16 = ((1 << (FIELD_WIDTH - 1)) - 1) & !1;
+
+/// フィールドデータ
+/// 色とビットボードを同期させて持ち、衝突判定などはビットボードで行う
+/// 保存するときは色だけを書き出し、読み込むときにビットボードを作り直す
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "Colors", into = "Colors")]
+pub struct Field {
+    colors: Colors,
+    bits: Bitboard,
+}
+
+impl Field {
+    pub const fn from_colors(colors: Colors) -> Self {
+        let mut bits = [0; FIELD_HEIGHT];
+        let mut y = 0;
+        while y < FIELD_HEIGHT {
+            let mut x = 0;
+            while x < FIELD_WIDTH {
+                if colors[y][x] != block_kind::NONE {
+                    bits[y] |= 1 << x;
+                }
+                x += 1;
+            }
+            y += 1;
+        }
+        Self { colors, bits }
+    }
+
+    pub fn colors(&self) -> &Colors {
+        &self.colors
+    }
+
+    pub fn bits(&self) -> &Bitboard {
+        &self.bits
+    }
+
+    /// セルの色を設定する
+    pub fn set(&mut self, x: usize, y: usize, color: BlockColor) {
+        self.colors[y][x] = color;
+        if color == block_kind::NONE {
+            self.bits[y] &= !(1 << x);
+        } else {
+            self.bits[y] |= 1 << x;
+        }
+    }
+
+    pub fn is_collision(&self, pos: &Position, mask: &BlockMask) -> bool {
+        is_collision_bits(&self.bits, pos, mask)
+    }
+
+    /// 壁以外にブロックがないか
+    pub fn is_empty(&self) -> bool {
+        self.colors[..(FIELD_HEIGHT - 2)].iter().all(|row| {
+            row.iter()
+                .all(|&color| color == block_kind::NONE || color == block_kind::WALL)
+        })
+    }
+
+    /// 壁になっていない列(一番上の行で判定する)
+    pub fn play_columns(&self) -> Vec<usize> {
+        (2..(FIELD_WIDTH - 2))
+            .filter(|&x| self.colors[0][x] != block_kind::WALL)
+            .collect()
+    }
+
+    /// 壁になっていない列を左右反転する
+    pub fn mirror(&mut self) {
+        let columns = self.play_columns();
+        for y in 0..(FIELD_HEIGHT - 2) {
+            let row = self.colors[y];
+            for (&x, &from) in columns.iter().zip(columns.iter().rev()) {
+                self.set(x, y, row[from]);
+            }
+        }
+    }
+
+    /// 一番上の行の壁のビット(ラインを消したときに一番上に入る行)
+    pub fn wall_row(&self) -> u16 {
+        (0..FIELD_WIDTH)
+            .filter(|&x| self.colors[0][x] == block_kind::WALL)
+            .fold(0, |bits, x| bits | 1 << x)
+    }
+
+    /// 揃っているラインを削除し、上のラインを下にずらす
+    /// 一番上に入る行は壁だけを残す
+    /// return: 消したライン数
+    pub fn erase_lines(&mut self) -> usize {
+        let top = self.colors[0].map(|color| match color {
+            block_kind::WALL => block_kind::WALL,
+            _ => block_kind::NONE,
+        });
+        let top_bits = self.wall_row();
+        let mut count = 0;
+        for y in 0..(FIELD_HEIGHT - 2) {
+            if self.bits[y] & FULL_ROW != FULL_ROW {
+                continue;
+            }
+            count += 1;
+            for y2 in (1..=y).rev() {
+                self.colors[y2] = self.colors[y2 - 1];
+                self.bits[y2] = self.bits[y2 - 1];
+            }
+            self.colors[0] = top;
+            self.bits[0] = top_bits;
+        }
+        count
+    }
+}
+
+impl From<Colors> for Field {
+    fn from(colors: Colors) -> Self {
+        Self::from_colors(colors)
+    }
+}
+
+impl From<Field> for Colors {
+    fn from(field: Field) -> Self {
+        field.colors
+    }
+}
+
+impl Index<usize> for Field {
+    type Output = [BlockColor; FIELD_WIDTH];
+
+    fn index(&self, y: usize) -> &Self::Output {
+        &self.colors[y]
+    }
+}
+
+/// フィールドの一番右の列を壁にして、ガイドラインのフィールド幅に合わせる
+pub fn narrow_field(field: &mut Field) {
+    for y in 0..(FIELD_HEIGHT - 2) {
+        field.set(GUIDELINE_WIDTH + 2, y, block_kind::WALL);
+    }
+}
+
+/// ビッグのフィールドの幅と高さ
+pub const BIG_WIDTH: usize = 5;
+pub const BIG_HEIGHT: usize = 10;
+/// ビッグのフィールドの左端の列(ブロックが出てくる位置で中央になるようにする)
+pub const BIG_LEFT: usize = 4;
+
+/// フィールドの左上の幅5、高さ10だけを残して壁にし、ビッグ用のフィールドにする
+/// 描画するときに1マスを2x2に広げる
+/// 壁の行が揃ったと判定されないように、一番右の列は上を壁で塞いで空けておく
+pub fn big_field(field: &mut Field) {
+    for y in 0..(FIELD_HEIGHT - 2) {
+        for x in 2..(FIELD_WIDTH - 2) {
+            let inside = (BIG_LEFT..(BIG_LEFT + BIG_WIDTH)).contains(&x) && y < BIG_HEIGHT;
+            let pocket = x == FIELD_WIDTH - 3 && y >= BIG_HEIGHT;
+            if !inside && !pocket {
+                field.set(x, y, block_kind::WALL);
+            }
+        }
+    }
+}
+
+/// ブロックの形をビットに変換する
+#[allow(clippy::needless_range_loop)]
+pub fn block_mask(block: &BlockShape) -> BlockMask {
+    let mut mask = [0; BLOCK_SIZE];
+    for y in 0..BLOCK_SIZE {
+        for x in 0..BLOCK_SIZE {
+            if block[y][x] != block_kind::NONE {
+                mask[y] |= 1 << x;
+            }
+        }
+    }
+    mask
+}
+
+/// ビットボード上での衝突判定
+/// フィールドの範囲外にはみ出した部分は無視する
+pub fn is_collision_bits(bits: &Bitboard, pos: &Position, mask: &BlockMask) -> bool {
+    mask.iter().enumerate().any(|(y, &row)| {
+        y + pos.y < FIELD_HEIGHT && (bits[y + pos.y] as u32) & ((row as u32) << pos.x) != 0
+    })
+}
+
+/// ビットボード上でブロックを落とした位置を返す
+/// セルのないブロック(出てくるのを待っている間)は落とさない
+pub fn drop_pos(bits: &Bitboard, pos: &Position, mask: &BlockMask) -> Position {
+    let mut pos = *pos;
+    if mask.iter().all(|&row| row == 0) {
+        return pos;
+    }
+    while !is_collision_bits(
+        bits,
+        &Position {
+            x: pos.x,
+            y: pos.y + 1,
+        },
+        mask,
+    ) {
+        pos.y += 1;
+    }
+    pos
+}
+
+/// ビットボードにブロックを置く
+pub fn place_bits(bits: &mut Bitboard, pos: &Position, mask: &BlockMask) {
+    for (y, &row) in mask.iter().enumerate() {
+        if y + pos.y < FIELD_HEIGHT {
+            bits[y + pos.y] |= ((row as u32) << pos.x) as u16;
+        }
+    }
+}
+
+/// ビットボード上で揃っているラインを削除する
+/// 一番上に入る行は `walls` (`Field::wall_row`) で埋める
+/// return: 消したライン数
+pub fn erase_bits(bits: &mut Bitboard, walls: u16) -> usize {
+    let mut count = 0;
+    for y in 0..(FIELD_HEIGHT - 2) {
+        if bits[y] & FULL_ROW == FULL_ROW {
+            count += 1;
+            bits.copy_within(0..y, 1);
+            bits[0] = walls;
+        }
+    }
+    count
+}
+
+const W: BlockColor = block_kind::WALL;
+pub const EMPTY_FIELD: Field = Field::from_colors([
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, W, W, W, W, W, W, W, W, W, W, W, W, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erase_bits_matches_erase_lines_on_big_field() {
+        let mut field = EMPTY_FIELD;
+        big_field(&mut field);
+        // 下の2行を埋め、その上に消えないブロックを置く
+        for y in [BIG_HEIGHT - 2, BIG_HEIGHT - 1] {
+            for x in BIG_LEFT..(BIG_LEFT + BIG_WIDTH) {
+                field.set(x, y, block_kind::I);
+            }
+        }
+        field.set(BIG_LEFT, BIG_HEIGHT - 3, block_kind::T);
+        let walls = field.wall_row();
+
+        let mut bits = *field.bits();
+        let count = erase_bits(&mut bits, walls);
+        assert_eq!(count, field.erase_lines());
+        assert_eq!(count, 2);
+        assert_eq!(&bits, field.bits());
+        assert_eq!(bits[0], walls);
+        assert_ne!(bits[0], EMPTY_ROW);
+    }
+}
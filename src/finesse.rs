@@ -0,0 +1,128 @@
+//! 最短の操作の探索
+//!
+//! ブロックが出てきた位置から、移動と回転だけで目的の位置に置くための最短の操作を探す
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    block::{block_kind, BlockShape},
+    game::{get_ghost_pos, move_block, rotate_left, rotate_right, Game, Position},
+};
+
+/// ブロックを置くまでの1回の操作(ハードドロップは含まない)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Left,
+    Right,
+    Down,
+    RotateRight,
+    RotateLeft,
+}
+
+impl Input {
+    pub const ALL: [Input; 5] = [
+        Input::Left,
+        Input::Right,
+        Input::Down,
+        Input::RotateRight,
+        Input::RotateLeft,
+    ];
+
+    /// ゲームに操作を反映する
+    pub fn apply(self, game: &mut Game) {
+        let pos = game.pos;
+        match self {
+            Input::Left => move_block(
+                game,
+                Position {
+                    x: pos.x.checked_sub(1).unwrap_or(pos.x),
+                    y: pos.y,
+                },
+            ),
+            Input::Right => move_block(
+                game,
+                Position {
+                    x: pos.x + 1,
+                    y: pos.y,
+                },
+            ),
+            Input::Down => move_block(
+                game,
+                Position {
+                    x: pos.x,
+                    y: pos.y + 1,
+                },
+            ),
+            Input::RotateRight => rotate_right(game),
+            Input::RotateLeft => rotate_left(game),
+        }
+    }
+
+    /// 操作を表す記号
+    pub fn to_symbol(self) -> &'static str {
+        match self {
+            Input::Left => "←",
+            Input::Right => "→",
+            Input::Down => "↓",
+            Input::RotateRight => "x",
+            Input::RotateLeft => "z",
+        }
+    }
+}
+
+/// ブロックが占めるセルの座標(左上から順)
+fn cells(pos: &Position, block: &BlockShape) -> Vec<(usize, usize)> {
+    let mut cells = vec![];
+    for (y, row) in block.iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            if cell != block_kind::NONE {
+                cells.push((x + pos.x, y + pos.y));
+            }
+        }
+    }
+    cells
+}
+
+/// 今のブロックをハードドロップしたときに占めるセル
+pub fn placed_cells(game: &Game) -> Vec<(usize, usize)> {
+    cells(
+        &get_ghost_pos(&game.field, &game.pos, &game.block),
+        &game.block,
+    )
+}
+
+/// ブロックが出てきた状態から、ハードドロップで `target` のセルに置くための最短の操作を探す
+/// 形が違っても占めるセルが同じなら同じ置き方とみなす
+/// 置けない場合は `None` を返す
+pub fn minimal_inputs(start: &Game, target: &[(usize, usize)]) -> Option<Vec<Input>> {
+    let mut target = target.to_vec();
+    target.sort_unstable();
+
+    let mut game = start.clone();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert((start.pos.x, start.pos.y, start.block));
+    queue.push_back((start.pos, start.block, vec![]));
+
+    // 幅優先探索
+    while let Some((pos, block, inputs)) = queue.pop_front() {
+        game.pos = pos;
+        game.block = block;
+        let mut placed = placed_cells(&game);
+        placed.sort_unstable();
+        if placed == target {
+            return Some(inputs);
+        }
+        for input in Input::ALL {
+            game.pos = pos;
+            game.block = block;
+            input.apply(&mut game);
+            if visited.insert((game.pos.x, game.pos.y, game.block)) {
+                let mut inputs = inputs.clone();
+                inputs.push(input);
+                queue.push_back((game.pos, game.block, inputs));
+            }
+        }
+    }
+    None
+}
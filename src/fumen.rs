@@ -0,0 +1,698 @@
+//! テト譜(fumen v115)のエンコードとデコード
+//!
+//! テト譜のフィールドは幅10、高さ23 + せり上がり用の1行なので、
+//! このゲームのフィールドに変換するときは一番右の列を壁にし(`narrow_field`)、21行目より上は捨てる
+//! 一番右の列にブロックがある盤面はテト譜に変換できない
+//!
+//! ホールドとネクストはコメントに `#Q=[ホールド](現在のブロック)ネクスト` の形式で書く
+
+use crate::{
+    block::{block_kind, BlockColor, BlockKind},
+    board::Board,
+    field::{narrow_field, Field, EMPTY_FIELD, FIELD_HEIGHT, GUIDELINE_WIDTH},
+    tbp::{Location, Orientation},
+};
+
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PREFIX: &str = "v115@";
+/// ホールドとネクストを書くコメントの接頭辞
+const QUEUE_PREFIX: &str = "#Q=";
+
+/// テト譜のフィールドの幅
+const WIDTH: usize = GUIDELINE_WIDTH;
+/// テト譜のフィールドの高さ(せり上がり用の行を除く)
+const TOP: usize = 23;
+/// テト譜のフィールドのセル数(せり上がり用の行を含む)
+const BLOCKS: usize = WIDTH * (TOP + 1);
+
+/// テト譜のフィールド
+/// cells[0] がせり上がり用の行で、cells[1] が一番下の行
+/// 値は 0: 空白, 1: I, 2: L, 3: O, 4: Z, 5: T, 6: J, 7: S, 8: お邪魔ブロック
+#[derive(Clone, PartialEq, Eq)]
+struct FumenField {
+    cells: [[u8; WIDTH]; TOP + 1],
+}
+
+impl FumenField {
+    fn new() -> Self {
+        Self {
+            cells: [[0; WIDTH]; TOP + 1],
+        }
+    }
+
+    /// yは一番下の行が0、せり上がり用の行が-1
+    fn get(&self, x: usize, y: i32) -> u8 {
+        self.cells[(y + 1) as usize][x]
+    }
+
+    fn set(&mut self, x: usize, y: i32, value: u8) {
+        self.cells[(y + 1) as usize][x] = value;
+    }
+
+    /// ブロックが全てフィールドの中にあるか(せり上がり用の行は含まない)
+    fn contains(location: &Location) -> bool {
+        location
+            .cells()
+            .iter()
+            .all(|&(x, y)| (0..WIDTH as i32).contains(&x) && (0..TOP as i32).contains(&y))
+    }
+
+    /// ブロックを置く(デコードではフィールドの外にあるブロックはエラーにしている)
+    fn fill(&mut self, location: &Location) {
+        for (x, y) in location.cells() {
+            if (0..WIDTH as i32).contains(&x) && (0..TOP as i32).contains(&y) {
+                self.set(x as usize, y, piece_value(location.kind));
+            }
+        }
+    }
+
+    /// 揃ったラインを消す
+    fn clear_lines(&mut self) {
+        let mut rows: Vec<_> = self.cells[1..]
+            .iter()
+            .filter(|row| row.contains(&0))
+            .copied()
+            .collect();
+        rows.resize(TOP, [0; WIDTH]);
+        self.cells[1..].copy_from_slice(&rows);
+    }
+
+    /// せり上がり用の行を下からせり上げる
+    fn rise(&mut self) {
+        self.cells.copy_within(0..TOP, 1);
+        self.cells[0] = [0; WIDTH];
+    }
+
+    /// 左右反転する
+    fn mirror(&mut self) {
+        for row in &mut self.cells[1..] {
+            row.reverse();
+        }
+    }
+
+    /// このゲームのフィールドに変換する
+    fn to_field(&self) -> Field {
+        let mut field = EMPTY_FIELD;
+        for y in 0..(FIELD_HEIGHT - 2) {
+            for x in 0..WIDTH {
+                let color = color_of(self.get(x, y as i32));
+                field.set(x + 2, FIELD_HEIGHT - 3 - y, color);
+            }
+        }
+        narrow_field(&mut field);
+        field
+    }
+
+    /// このゲームのフィールドから変換する
+    fn from_field(field: &Field) -> Self {
+        let mut fumen = Self::new();
+        for y in 0..(FIELD_HEIGHT - 2) {
+            for x in 0..WIDTH {
+                let value = value_of(field[FIELD_HEIGHT - 3 - y][x + 2]);
+                fumen.set(x, y as i32, value);
+            }
+        }
+        fumen
+    }
+}
+
+fn piece_value(kind: BlockKind) -> u8 {
+    match kind {
+        BlockKind::I => 1,
+        BlockKind::L => 2,
+        BlockKind::O => 3,
+        BlockKind::Z => 4,
+        BlockKind::T => 5,
+        BlockKind::J => 6,
+        BlockKind::S => 7,
+    }
+}
+
+fn piece_of(value: u8) -> Option<BlockKind> {
+    BlockKind::ALL
+        .into_iter()
+        .find(|&kind| piece_value(kind) == value)
+}
+
+fn color_of(value: u8) -> BlockColor {
+    match value {
+        0 => block_kind::NONE,
+        value => piece_of(value).map_or(block_kind::GARBAGE, BlockKind::color),
+    }
+}
+
+fn value_of(color: BlockColor) -> u8 {
+    match color {
+        block_kind::NONE => 0,
+        color => BlockKind::from_color(color).map_or(8, piece_value),
+    }
+}
+
+fn rotation_value(orientation: Orientation) -> usize {
+    match orientation {
+        Orientation::South => 0,
+        Orientation::East => 1,
+        Orientation::North => 2,
+        Orientation::West => 3,
+    }
+}
+
+/// テト譜の中心座標とSRSの中心座標のずれ
+fn center_offset(kind: BlockKind, orientation: Orientation) -> (i32, i32) {
+    match (kind, orientation) {
+        (BlockKind::O, Orientation::West) => (1, -1),
+        (BlockKind::O, Orientation::South) => (1, 0),
+        (BlockKind::O, Orientation::North) => (0, -1),
+        (BlockKind::I, Orientation::South) => (1, 0),
+        (BlockKind::I, Orientation::West) => (0, -1),
+        (BlockKind::S, Orientation::North) => (0, -1),
+        (BlockKind::S, Orientation::East) => (-1, 0),
+        (BlockKind::Z, Orientation::North) => (0, -1),
+        (BlockKind::Z, Orientation::West) => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+/// テト譜の1ページ
+#[derive(Clone)]
+pub struct Page {
+    pub field: Field,
+    pub piece: Option<Location>, // このページで操作しているブロック
+    pub comment: String,
+    fumen_field: FumenField,
+    lock: bool,
+}
+
+impl Page {
+    /// このページの盤面
+    /// コメントにネクストがなければ、操作しているブロックを現在のブロックにし、
+    /// 以降のページのブロックをネクストにする
+    pub fn board(pages: &[Page], index: usize) -> Option<Board> {
+        let page = pages.get(index)?;
+        if let Some((hold, current, queue)) = parse_queue(&page.comment) {
+            return Some(Board {
+                field: page.field,
+                current,
+                hold,
+                queue,
+            });
+        }
+        Some(Board {
+            field: page.field,
+            current: page.piece.map(|piece| piece.kind),
+            hold: None,
+            queue: pages[(index + 1)..]
+                .iter()
+                .filter_map(|page| page.piece.map(|piece| piece.kind))
+                .collect(),
+        })
+    }
+}
+
+impl TryFrom<&Board> for Page {
+    type Error = String;
+
+    /// 一番右の列(テト譜にない列)にブロックがある場合はエラーを返す
+    fn try_from(board: &Board) -> Result<Self, String> {
+        let x = GUIDELINE_WIDTH + 2;
+        let extra = (0..(FIELD_HEIGHT - 2))
+            .any(|y| !matches!(board.field[y][x], block_kind::NONE | block_kind::WALL));
+        if extra {
+            return Err(format!(
+                "column {} has blocks, but fumen fields are {GUIDELINE_WIDTH} columns wide",
+                GUIDELINE_WIDTH + 1
+            ));
+        }
+        Ok(Self {
+            field: board.field,
+            piece: None,
+            comment: queue_comment(board),
+            fumen_field: FumenField::from_field(&board.field),
+            lock: true,
+        })
+    }
+}
+
+/// `#Q=[T](I)OSZ` のようなコメントを読み込む
+fn parse_queue(comment: &str) -> Option<QueueComment> {
+    let text = comment.strip_prefix(QUEUE_PREFIX)?;
+    let piece = |c: char| BlockKind::from_char(c);
+    let (hold, text) = match text.strip_prefix('[') {
+        Some(text) => {
+            let (hold, text) = text.split_once(']')?;
+            (hold.chars().next().and_then(piece), text)
+        }
+        None => (None, text),
+    };
+    let (current, text) = match text.strip_prefix('(') {
+        Some(text) => {
+            let (current, text) = text.split_once(')')?;
+            (current.chars().next().and_then(piece), text)
+        }
+        None => (None, text),
+    };
+    let queue = text
+        .chars()
+        .take_while(|c| !c.is_whitespace())
+        .map(piece)
+        .collect::<Option<Vec<_>>>()?;
+    Some((hold, current, queue))
+}
+
+/// 盤面のホールドとネクストをコメントにする
+fn queue_comment(board: &Board) -> String {
+    if board.hold.is_none() && board.current.is_none() && board.queue.is_empty() {
+        return String::new();
+    }
+    let mut comment = String::from(QUEUE_PREFIX);
+    if let Some(hold) = board.hold {
+        comment += &format!("[{}]", hold.to_char());
+    }
+    if let Some(current) = board.current {
+        comment += &format!("({})", current.to_char());
+    }
+    comment.extend(board.queue.iter().map(|kind| kind.to_char()));
+    comment
+}
+
+/// ホールド、現在のブロック、ネクスト
+type QueueComment = (Option<BlockKind>, Option<BlockKind>, Vec<BlockKind>);
+
+/// 64進数の値を読み書きするバッファ
+struct Buffer {
+    values: Vec<usize>,
+    index: usize,
+}
+
+impl Buffer {
+    fn poll(&mut self, len: usize) -> Result<usize, String> {
+        let mut value = 0;
+        for i in 0..len {
+            let v = *self
+                .values
+                .get(self.index)
+                .ok_or("unexpected end of data")?;
+            value += v * 64usize.pow(i as u32);
+            self.index += 1;
+        }
+        Ok(value)
+    }
+
+    fn push(&mut self, mut value: usize, len: usize) {
+        for _ in 0..len {
+            self.values.push(value % 64);
+            value /= 64;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.index >= self.values.len()
+    }
+}
+
+/// テト譜をデコードする
+/// `https://fumen.zui.jp/?v115@...` のようなURLも受け付ける
+pub fn decode(fumen: &str) -> Result<Vec<Page>, String> {
+    let start = fumen.find(PREFIX).ok_or("only fumen v115 is supported")?;
+    let values = fumen[(start + PREFIX.len())..]
+        .chars()
+        .filter(|&c| c != '?' && !c.is_whitespace())
+        .map(|c| {
+            ENCODE_TABLE
+                .iter()
+                .position(|&e| e as char == c)
+                .ok_or_else(|| format!("invalid character `{c}`"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut buffer = Buffer { values, index: 0 };
+
+    let mut pages: Vec<Page> = vec![];
+    let mut prev = FumenField::new();
+    let mut repeat = 0;
+    let mut comment = String::new();
+
+    while !buffer.is_empty() {
+        // フィールド
+        let mut field = prev.clone();
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            let mut changed = true;
+            while index < BLOCKS {
+                let value = buffer.poll(2)?;
+                let diff = (value / BLOCKS) as i32 - 8;
+                let count = value % BLOCKS + 1;
+                if diff == 0 && count == BLOCKS {
+                    changed = false;
+                }
+                for _ in 0..count {
+                    if index >= BLOCKS {
+                        return Err("invalid field data".into());
+                    }
+                    let (x, y) = (index % WIDTH, TOP as i32 - (index / WIDTH) as i32 - 1);
+                    let cell = field.get(x, y) as i32 + diff;
+                    field.set(x, y, cell.clamp(0, 8) as u8);
+                    index += 1;
+                }
+            }
+            if !changed {
+                repeat = buffer.poll(1)?;
+            }
+        }
+
+        // 操作
+        let mut value = buffer.poll(3)?;
+        let kind = value % 8;
+        value /= 8;
+        let orientation = match value % 4 {
+            0 => Orientation::South,
+            1 => Orientation::East,
+            2 => Orientation::North,
+            _ => Orientation::West,
+        };
+        value /= 4;
+        let coordinate = value % BLOCKS;
+        value /= BLOCKS;
+        let rise = value % 2 == 1;
+        value /= 2;
+        let mirror = value % 2 == 1;
+        value /= 2;
+        value /= 2; // colorize
+        let has_comment = value % 2 == 1;
+        value /= 2;
+        let lock = value % 2 == 0;
+
+        let piece = piece_of(kind as u8).map(|kind| {
+            let (dx, dy) = center_offset(kind, orientation);
+            Location {
+                kind,
+                orientation,
+                x: (coordinate % WIDTH) as i32 + dx,
+                y: TOP as i32 - (coordinate / WIDTH) as i32 - 1 + dy,
+            }
+        });
+        if piece.is_some_and(|piece| !FumenField::contains(&piece)) {
+            return Err(format!(
+                "page {}: piece is outside the field",
+                pages.len() + 1
+            ));
+        }
+
+        // コメント
+        if has_comment {
+            let len = buffer.poll(2)?;
+            let mut escaped = String::new();
+            for _ in 0..len.div_ceil(4) {
+                let mut value = buffer.poll(5)?;
+                for _ in 0..4 {
+                    escaped.push(char::from((value % 96 + 32) as u8));
+                    value /= 96;
+                }
+            }
+            escaped.truncate(len);
+            comment = unescape(&escaped);
+        }
+
+        pages.push(Page {
+            field: field.to_field(),
+            piece,
+            comment: comment.clone(),
+            fumen_field: field.clone(),
+            lock,
+        });
+
+        // 次のページのフィールド
+        if lock {
+            if let Some(piece) = &piece {
+                field.fill(piece);
+            }
+            field.clear_lines();
+            if rise {
+                field.rise();
+            }
+            if mirror {
+                field.mirror();
+            }
+        }
+        prev = field;
+    }
+
+    Ok(pages)
+}
+
+/// テト譜にエンコードする
+pub fn encode(pages: &[Page]) -> String {
+    let mut buffer = Buffer {
+        values: vec![],
+        index: 0,
+    };
+    let mut prev = FumenField::new();
+    let mut prev_comment = "";
+    let mut repeat_index: Option<usize> = None;
+
+    for (i, page) in pages.iter().enumerate() {
+        // フィールドの差分を連長圧縮する
+        let current = &page.fumen_field;
+        let diff_at = |index: usize| {
+            let (x, y) = (index % WIDTH, TOP as i32 - (index / WIDTH) as i32 - 1);
+            (current.get(x, y) as i32 - prev.get(x, y) as i32 + 8) as usize
+        };
+        let mut values = Buffer {
+            values: vec![],
+            index: 0,
+        };
+        let mut changed = false;
+        let mut run = (diff_at(0), 0);
+        for index in 1..BLOCKS {
+            let diff = diff_at(index);
+            if diff == run.0 {
+                run.1 += 1;
+            } else {
+                values.push(run.0 * BLOCKS + run.1, 2);
+                run = (diff, 0);
+                changed = true;
+            }
+        }
+        values.push(run.0 * BLOCKS + run.1, 2);
+
+        match repeat_index {
+            Some(index) if !changed && buffer.values[index] < ENCODE_TABLE.len() - 1 => {
+                // フィールドは記録せず、繰り返し回数を増やす
+                buffer.values[index] += 1;
+            }
+            _ if !changed => {
+                buffer.values.extend(values.values);
+                buffer.push(0, 1);
+                repeat_index = Some(buffer.values.len() - 1);
+            }
+            _ => {
+                buffer.values.extend(values.values);
+                repeat_index = None;
+            }
+        }
+
+        // 操作
+        let (kind, rotation, coordinate) = match &page.piece {
+            Some(piece) => {
+                let (dx, dy) = center_offset(piece.kind, piece.orientation);
+                let (x, y) = (piece.x - dx, piece.y - dy);
+                (
+                    piece_value(piece.kind) as usize,
+                    rotation_value(piece.orientation),
+                    ((TOP as i32 - y - 1) * WIDTH as i32 + x) as usize,
+                )
+            }
+            None => (0, 0, 0),
+        };
+        let colorize = i == 0;
+        let has_comment = page.comment != prev_comment;
+        let mut value = usize::from(!page.lock);
+        value = value * 2 + usize::from(has_comment);
+        value = value * 2 + usize::from(colorize);
+        value *= 2; // 反転なし
+        value *= 2; // せり上がりなし
+        value = value * BLOCKS + coordinate;
+        value = value * 4 + rotation;
+        value = value * 8 + kind;
+        buffer.push(value, 3);
+
+        // コメント
+        if has_comment {
+            let escaped: Vec<u8> = escape(&page.comment).into_bytes();
+            buffer.push(escaped.len(), 2);
+            for chunk in escaped.chunks(4) {
+                let value = chunk
+                    .iter()
+                    .rev()
+                    .fold(0, |value, &c| value * 96 + (c - 32) as usize);
+                buffer.push(value, 5);
+            }
+            prev_comment = &page.comment;
+        }
+
+        // 次のページのフィールド
+        let mut field = current.clone();
+        if page.lock {
+            if let Some(piece) = &page.piece {
+                field.fill(piece);
+            }
+            field.clear_lines();
+        }
+        prev = field;
+    }
+
+    let data: String = buffer
+        .values
+        .iter()
+        .map(|&value| ENCODE_TABLE[value] as char)
+        .collect();
+
+    // 先頭の5文字と合わせて47文字ごとに?を挟む
+    let mut fumen = String::from(PREFIX);
+    for (i, c) in data.chars().enumerate() {
+        if i >= 42 && (i - 42) % 47 == 0 {
+            fumen.push('?');
+        }
+        fumen.push(c);
+    }
+    fumen
+}
+
+/// JavaScriptのescapeと同じようにエンコードする
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '*' | '_' | '+' | '-' | '.' | '/' => {
+                result.push(c)
+            }
+            c if (c as u32) < 0x100 => result += &format!("%{:02X}", c as u32),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    result += &format!("%u{:04X}", unit);
+                }
+            }
+        }
+    }
+    result
+}
+
+/// JavaScriptのescapeでエンコードされたコメントを戻す
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let len = if chars.peek() == Some(&'u') {
+            chars.next();
+            4
+        } else {
+            2
+        };
+        let hex: String = chars.by_ref().take(len).collect();
+        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+            Some(c) => result.push(c),
+            None => {
+                result.push('%');
+                result.push_str(&hex);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        field::{FIELD_WIDTH, FULL_ROW},
+        game::{self, Game},
+        tbp::Orientation,
+    };
+
+    /// 空のフィールド
+    const EMPTY: &str = "v115@vhAAgH";
+    /// 一番下の行の10列が全て埋まったフィールド
+    const FULL_ROW_FUMEN: &str = "v115@bhJ8JeAgH";
+    /// 下2行の右端以外が埋まったフィールドと、右端の一番下から縦向きのIブロック
+    const I_PIECE: &str = "v115@RhI8AeI8KepIJ";
+
+    #[test]
+    fn decode_and_encode_reference() {
+        for fumen in [EMPTY, FULL_ROW_FUMEN, I_PIECE] {
+            let pages = decode(fumen).unwrap();
+            assert_eq!(encode(&pages), fumen);
+        }
+    }
+
+    #[test]
+    fn decode_field_and_piece() {
+        let pages = decode(I_PIECE).unwrap();
+        assert_eq!(pages.len(), 1);
+        let page = &pages[0];
+        for y in [FIELD_HEIGHT - 3, FIELD_HEIGHT - 4] {
+            for x in 2..11 {
+                assert_eq!(page.field[y][x], block_kind::GARBAGE);
+            }
+            assert_eq!(page.field[y][11], block_kind::NONE);
+        }
+        let piece = page.piece.unwrap();
+        assert_eq!(piece.kind, BlockKind::I);
+        assert_eq!(piece.orientation, Orientation::East);
+        assert_eq!((piece.x, piece.y), (9, 2));
+        assert_eq!(piece.cells(), [(9, 3), (9, 2), (9, 1), (9, 0)]);
+    }
+
+    #[test]
+    fn piece_outside_field_is_rejected() {
+        // 中心がy=1の縦向きのIブロックは1セルが床より下にある
+        assert_eq!(
+            decode("v115@RhI8AeI8KepNJ").err().as_deref(),
+            Some("page 1: piece is outside the field")
+        );
+    }
+
+    #[test]
+    fn imported_field_is_narrow() {
+        let pages = decode(EMPTY).unwrap();
+        for y in 0..(FIELD_HEIGHT - 2) {
+            assert_eq!(pages[0].field[y][FIELD_WIDTH - 3], block_kind::WALL);
+        }
+    }
+
+    #[test]
+    fn full_row_clears_after_import() {
+        let board = Page::board(&decode(FULL_ROW_FUMEN).unwrap(), 0).unwrap();
+        assert_eq!(board.field.bits()[FIELD_HEIGHT - 3] & FULL_ROW, FULL_ROW);
+
+        let mut game = Game::with_seed(0);
+        board.apply(&mut game);
+        game::hard_drop(&mut game);
+        game::lock_piece(&mut game).unwrap();
+        assert_eq!(game.line, 1);
+    }
+
+    #[test]
+    fn board_round_trip() {
+        let board = Page::board(&decode(I_PIECE).unwrap(), 0).unwrap();
+        let text = board.to_text();
+        let page = Page::try_from(&Board::parse(&text).unwrap()).unwrap();
+        let decoded = Page::board(&decode(&encode(&[page])).unwrap(), 0).unwrap();
+        assert_eq!(decoded.field.colors(), board.field.colors());
+        assert_eq!(decoded.current, Some(BlockKind::I));
+    }
+
+    #[test]
+    fn extra_column_cannot_be_exported() {
+        let mut board = Board::parse("...........").unwrap();
+        board
+            .field
+            .set(FIELD_WIDTH - 3, FIELD_HEIGHT - 3, block_kind::GARBAGE);
+        assert!(Page::try_from(&board).is_err());
+    }
+}
@@ -1,14 +1,22 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, error::Error, fmt, time::Instant};
 
-use crate::block::{
-    block_kind::{self, WALL as W},
-    gen_block_7, BlockColor, BlockKind, BlockShape, BLOCKS, COLOR_TABLE,
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arika,
+    block::{block_kind, gen_bag, shape_serde, BlockKind, BlockShape, BLOCK_SIZE},
+    event::{Event, MAX_EVENTS},
+    field::{
+        big_field, block_mask, drop_pos, Field, EMPTY_FIELD, FIELD_HEIGHT, FIELD_WIDTH, FULL_ROW,
+    },
+    pieces,
+    rules::{Modifier, RotationSystem, Rules, MIRROR_INTERVAL},
+    stats::Stats,
 };
 
-pub const FIELD_WIDTH: usize = 11 + 2 + 2; //  フィールド + 壁 + 番兵
-pub const FIELD_HEIGHT: usize = 20 + 1 + 1; // フィールド + 底 + 番兵
-pub type Field = [[BlockColor; FIELD_WIDTH]; FIELD_HEIGHT];
-pub const NEXT_LENGTH: usize = 3;
+/// 消したライン数ごとのスコア(5段以上は4段消しとして扱う)
 pub const SCORE_TABLE: [usize; 5] = [
     0,   // 0段消し
     1,   // 1段消し
@@ -17,7 +25,34 @@ pub const SCORE_TABLE: [usize; 5] = [
     125, // 4段消し
 ];
 
-#[derive(Clone, Copy)]
+/// ゲームオーバーの理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameOverReason {
+    /// 出てきたブロックが既にあるブロックと重なった
+    BlockOut,
+    /// ブロックが全て出てくる位置より上で固定された
+    LockOut,
+    /// せり上がりでブロックがフィールドの上にはみ出した
+    GarbageTopOut,
+    /// 決められたネクストを使い切った
+    OutOfPieces,
+}
+
+impl fmt::Display for GameOverReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GameOverReason::BlockOut => "block out",
+            GameOverReason::LockOut => "lock out",
+            GameOverReason::GarbageTopOut => "garbage top out",
+            GameOverReason::OutOfPieces => "out of pieces",
+        })
+    }
+}
+
+impl Error for GameOverReason {}
+
+/// フィールド上のブロックの左上の位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -29,380 +64,611 @@ impl Position {
     }
 }
 
-#[derive(Clone)]
+/// セルにブロックを固定した時刻(インビジブル用)
+pub type LockTimes = [[Option<Instant>; FIELD_WIDTH]; FIELD_HEIGHT];
+
+/// ゲームの状態
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Game {
-    pub field: Field,                   // フィールドデータ(裏データ)
-    pub pos: Position,                  // 現在のブロックの位置
-    pub block: BlockShape,              // 現在のブロック
-    pub hold: Option<BlockShape>,       // ホールドしたブロック
-    pub holded: bool,                   // ホールド済みか
-    pub next: VecDeque<BlockShape>,     // 次のブロック(3つ)
-    pub next_buf: VecDeque<BlockShape>, // 次のブロックのバッファ(1~7つ)
-    pub score: usize,                   // 現在のスコア
-    pub line: usize,                    // 消したライン数
+    pub field: Field,  // フィールドデータ(裏データ)
+    pub pos: Position, // 現在のブロックの位置
+    #[serde(deserialize_with = "shape_serde::shape")]
+    pub block: BlockShape, // 現在のブロック
+    #[serde(deserialize_with = "shape_serde::option")]
+    pub hold: Option<BlockShape>, // ホールドしたブロック
+    pub holded: bool,  // ホールド済みか
+    #[serde(deserialize_with = "shape_serde::queue")]
+    pub next: VecDeque<BlockShape>, // 次のブロック(ルールの表示数だけ、最低1つ)
+    #[serde(deserialize_with = "shape_serde::queue")]
+    pub next_buf: VecDeque<BlockShape>, // 次のブロックのバッファ(ピースセットの数まで)
+    pub score: usize,  // 現在のスコア
+    pub line: usize,   // 消したライン数
+    pub combo: usize,  // 連続でラインを消した回数
+    pub rng: ChaCha8Rng, // ブロック生成用の乱数
+    pub seed: u64,     // 乱数のシード値
+    pub fixed_queue: bool, // ネクストを補充しない(決められた順番で出す)
+    pub rotated: bool, // 最後の操作が回転か(Tスピン判定用)
+    pub stats: Stats,  // 統計
+    #[serde(default)]
+    pub rules: Rules, // ルール
+    #[serde(skip)]
+    pub events: VecDeque<Event>, // まだ取り出されていないイベント
+    #[serde(skip)]
+    pub lock_times: LockTimes, // セルにブロックを固定した時刻(インビジブルの場合のみ)
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Game {
+        Game::with_seed(rand::random())
+    }
+
+    /// シード値を指定してゲームを初期化する
+    /// 同じシード値なら同じ順番でブロックが出てくる
+    pub fn with_seed(seed: u64) -> Game {
+        Game::with_rules(seed, Rules::default())
+    }
+
+    /// シード値とルールを指定してゲームを初期化する
+    pub fn with_rules(seed: u64, rules: Rules) -> Game {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
         let mut game = Game {
-            field: [
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, W, W, W, W, W, W, W, W, W, W, W, W, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-            ],
+            field: EMPTY_FIELD,
             pos: Position::init(),
-            block: BLOCKS[rand::random::<BlockKind>() as usize],
+            block: pieces::current().random(&mut rng),
             hold: None,
             holded: false,
-            next: gen_block_7().into(),
-            next_buf: gen_block_7().into(),
+            next: VecDeque::new(),
+            next_buf: gen_bag(&mut rng).into(),
             score: 0,
             line: 0,
+            combo: 0,
+            rng,
+            seed,
+            fixed_queue: false,
+            rotated: false,
+            stats: Stats::new(),
+            rules,
+            events: VecDeque::new(),
+            lock_times: LockTimes::default(),
         };
+        if rules.has(Modifier::Big) {
+            big_field(&mut game.field);
+        }
         // 初期ブロックを供給
+        fill_next(&mut game);
         spawn_block(&mut game).ok();
         game
     }
-}
-
-pub fn is_collision(field: &Field, pos: &Position, block: &BlockShape) -> bool {
-    for y in 0..4 {
-        for x in 0..4 {
-            // ブロックの範囲外は無視
-            if y + pos.y >= FIELD_HEIGHT || x + pos.x >= FIELD_WIDTH {
-                continue;
-            };
-
-            // ブロックがすでにある場所への衝突、フィールドの外壁への衝突の場合はTrueを返す
-            if block[y][x] != block_kind::NONE && field[y + pos.y][x + pos.x] != block_kind::NONE {
-                // ブロックとフィールドのどちらも何かしらのブロックがある場合は衝突してる
-                return true;
-            };
-        }
-    }
-    false
-}
-
-fn get_ghost_pos(field: &Field, pos: &Position, block: &BlockShape) -> Position {
-    let mut ghost_pos = *pos;
-    loop {
-        let new_pos = Position {
-            x: ghost_pos.x,
-            y: ghost_pos.y + 1,
-        };
-        if is_collision(field, &new_pos, block) {
-            break;
-        } else {
-            ghost_pos = new_pos;
-        }
-    }
-    ghost_pos
-}
-
-#[allow(clippy::needless_range_loop)]
-pub fn draw(
-    Game {
-        field,
-        pos,
-        block,
-        hold,
-        next,
-        score,
-        ..
-    }: &Game,
-) {
-    // 裏データの生成
-    let mut field_buf = *field;
-
-    // 裏データにゴーストブロックを書き込む
-    let ghost_pos = get_ghost_pos(field, pos, block);
-    for y in 0..4 {
-        for x in 0..4 {
-            if block[y][x] != block_kind::NONE {
-                field_buf[y + ghost_pos.y][x + ghost_pos.x] = block_kind::GHOST;
-            }
-        }
-    }
 
-    // 裏データにブロックを書き込む
-    for y in 0..4 {
-        for x in 0..4 {
-            if block[y][x] != block_kind::NONE {
-                field_buf[y + pos.y][x + pos.x] = block[y][x];
-            }
-        }
+    /// レベル(10ライン消すごとに1つ上がる)
+    pub fn level(&self) -> usize {
+        self.line / 10
     }
 
-    // ホールドを描画
-    println!("\x1b[2;28HHOLD");
-    if let Some(hold) = hold {
-        for y in 0..4 {
-            print!("\x1b[{};28H", y + 3);
-            for x in 0..4 {
-                print!("{}", COLOR_TABLE[hold[y][x]]);
-            }
-            println!();
+    /// イベントを溜める
+    pub fn emit(&mut self, event: Event) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
         }
+        self.events.push_back(event);
     }
 
-    // 3つのネクストブロックたちを描画
-    println!("\x1b[8;28HNEXT"); // カーソルをネクスト位置に移動
-    for (i, next) in next.iter().take(NEXT_LENGTH).enumerate() {
-        for y in 0..4 {
-            print!("\x1b[{};28H", i * 4 + y + 9); // カーソルを移動
-            for x in 0..4 {
-                print!("{}", COLOR_TABLE[next[y][x]]);
-            }
-            println!();
-        }
+    /// 溜まったイベントを古い順に取り出す
+    pub fn drain_events(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.events.drain(..)
     }
+}
 
-    // スコアを描画
-    println!("\x1b[22;28H{score}");
-
-    // 裏データの描画
-    println!("\x1b[H"); // カーソルを先頭へ移動
-    for y in 0..(FIELD_HEIGHT - 1) {
-        for x in 1..(FIELD_WIDTH - 1) {
-            print!("{}", COLOR_TABLE[field_buf[y][x]])
-        }
-        println!();
-    }
+/// ブロックがフィールドに衝突するか
+pub fn is_collision(field: &Field, pos: &Position, block: &BlockShape) -> bool {
+    field.is_collision(pos, &block_mask(block))
+}
 
-    // 色情報をリセット
-    println!("\x1b[0m");
+/// ブロックをそのまま落としたときの位置
+pub fn get_ghost_pos(field: &Field, pos: &Position, block: &BlockShape) -> Position {
+    drop_pos(field.bits(), pos, &block_mask(block))
 }
 
 /// ブロックをフィールドに固定する
+#[allow(clippy::needless_range_loop)]
 pub fn fix_block(
     Game {
         field, pos, block, ..
     }: &mut Game,
 ) {
-    for y in 0..4 {
-        for x in 0..4 {
+    for y in 0..BLOCK_SIZE {
+        for x in 0..BLOCK_SIZE {
             if block[y][x] != block_kind::NONE {
-                field[y + pos.y][x + pos.x] = block[y][x];
+                field.set(x + pos.x, y + pos.y, block[y][x]);
             }
         }
     }
 }
 
+/// ルール上、今ホールドできるか
+pub fn can_hold(game: &Game) -> bool {
+    game.rules.can_hold(game.holded)
+}
+
 /// ホールド処理
 /// - 1回目のホールドは現在のブロックをホールド
 /// - 2回目以降のホールドは現在のブロックとホールドを交換
-/// - 現在のブロックに対して既にホールドしている場合は何もしない
+/// - ルール上ホールドできない場合は何もしない
 pub fn hold(game: &mut Game) {
-    if game.holded {
-        // 現在のブロックに対して既にホールドしている場合は何もしない
+    if !can_hold(game) {
+        // 現在のブロックに対して既にホールドしている場合などは何もしない
         return;
     }
     if let Some(mut hold) = game.hold {
         // ホールドの交換
         std::mem::swap(&mut hold, &mut game.block);
         game.hold = Some(hold);
-        game.pos = Position::init();
+        game.block = spawn_shape(&game.rules, &game.block);
+        game.pos = spawn_pos(&game.block);
+        game.rotated = false;
+        game.emit(Event::HoldUsed {
+            kind: BlockKind::from_shape(&hold),
+        });
+    } else if game.fixed_queue && game.next.is_empty() {
+        // 決められたネクストがもうない場合はホールドできない
+        return;
     } else {
         // ホールドして、新しいブロックを生成
         game.hold = Some(game.block);
+        game.emit(Event::HoldUsed {
+            kind: BlockKind::from_shape(&game.block),
+        });
         spawn_block(game).ok();
     }
     // ホールドしたのでフラグを立てる
     game.holded = true;
 }
 
-/// ラインが揃っているかチェックし、揃っている場合は削除する
-/// return: 消したライン数
-pub fn erace_line(field: &mut Field) -> usize {
-    let mut count = 0;
-    for y in 1..FIELD_HEIGHT - 2 {
-        // ラインが揃っているかチェック
-        let mut can_erace = true;
-        for x in 2..(FIELD_WIDTH - 2) {
-            if field[y][x] == 0 {
-                can_erace = false;
-                break;
-            }
-        }
-
-        // ラインを削除
-        if can_erace {
-            count += 1;
-            for y2 in (2..=y).rev() {
-                field[y2] = field[y2 - 1];
-            }
-        }
-    }
-    count
-}
-
 /// ブロックを指定した座標へ移動できるなら移動する
 pub fn move_block(game: &mut Game, new_pos: Position) {
     if !is_collision(&game.field, &new_pos, &game.block) {
         game.pos = new_pos;
+        game.rotated = false;
     }
 }
 
-/// 右に90度回転する
+/// ブロックの形を右に90度回転させたものを返す
+/// ピースセットで決めた大きさの正方形の中で回転する
 #[allow(clippy::needless_range_loop)]
-pub fn rotate_right(game: &mut Game) {
+pub fn rotate_shape_right(block: &BlockShape) -> BlockShape {
+    let size = pieces::current().size_of(block);
     let mut new_shape: BlockShape = Default::default();
-    for y in 0..4 {
-        for x in 0..4 {
-            new_shape[y][x] = game.block[4 - 1 - x][y];
+    for y in 0..size {
+        for x in 0..size {
+            new_shape[y][x] = block[size - 1 - x][y];
         }
     }
+    new_shape
+}
+
+/// ブロックの形を左に90度回転させたものを返す
+#[allow(clippy::needless_range_loop)]
+pub fn rotate_shape_left(block: &BlockShape) -> BlockShape {
+    let size = pieces::current().size_of(block);
+    let mut new_shape: BlockShape = Default::default();
+    for y in 0..size {
+        for x in 0..size {
+            new_shape[size - 1 - x][y] = block[y][x];
+        }
+    }
+    new_shape
+}
+
+/// 右に90度回転する
+pub fn rotate_right(game: &mut Game) {
+    if game.rules.rotation == RotationSystem::Arika {
+        arika::rotate(game, true);
+        return;
+    }
+    let new_shape = rotate_shape_right(&game.block);
     if !is_collision(&game.field, &game.pos, &new_shape) {
         game.block = new_shape;
-    } else if let Ok(new_pos) = super_rotation(&game.field, &game.pos, &new_shape) {
+        game.rotated = true;
+    } else if let Some(new_pos) = super_rotation(&game.field, &game.pos, &new_shape) {
         game.pos = new_pos;
         game.block = new_shape;
+        game.rotated = true;
     }
 }
 
 /// 左に90度回転する
 pub fn rotate_left(game: &mut Game) {
-    let mut new_shape: BlockShape = Default::default();
-    for y in 0..4 {
-        for x in 0..4 {
-            new_shape[4 - 1 - x][y] = game.block[y][x];
-        }
+    if game.rules.rotation == RotationSystem::Arika {
+        arika::rotate(game, false);
+        return;
     }
+    let new_shape = rotate_shape_left(&game.block);
     if !is_collision(&game.field, &game.pos, &new_shape) {
         game.block = new_shape;
-    } else if let Ok(new_pos) = super_rotation(&game.field, &game.pos, &new_shape) {
+        game.rotated = true;
+    } else if let Some(new_pos) = super_rotation(&game.field, &game.pos, &new_shape) {
         game.pos = new_pos;
         game.block = new_shape;
+        game.rotated = true;
     }
 }
 
 /// スーパーローテーション
-fn super_rotation(field: &Field, pos: &Position, block: &BlockShape) -> Result<Position, ()> {
-    let diff_pos = [
-        // 上へ1マスずらす
-        Position {
-            x: pos.x,
-            y: pos.y.checked_sub(1).unwrap_or(pos.y),
-        },
-        // 右へ1マスずらす
-        Position {
-            x: pos.x.checked_add(1).unwrap_or(pos.x),
-            y: pos.y,
-        },
-        // 下へ1マスずらす
-        Position {
-            x: pos.x,
-            y: pos.y.checked_add(1).unwrap_or(pos.y),
-        },
-        // 左へ1マスずらす
-        Position {
-            x: pos.x.checked_sub(1).unwrap_or(pos.x),
-            y: pos.y,
-        },
-        // 上へ2マスずらす
-        Position {
-            x: pos.x,
-            y: pos.y.checked_sub(2).unwrap_or(pos.y),
-        },
-        // 右へ2マスずらす
-        Position {
-            x: pos.x.checked_add(2).unwrap_or(pos.x),
-            y: pos.y,
-        },
-        // 下へ2マスずらす
-        Position {
-            x: pos.x,
-            y: pos.y.checked_add(2).unwrap_or(pos.y),
-        },
-        // 左へ2マスずらす
-        Position {
-            x: pos.x.checked_sub(2).unwrap_or(pos.x),
-            y: pos.y,
-        },
-    ];
-    for pos in diff_pos {
-        if !is_collision(field, &pos, block) {
-            return Ok(pos);
+/// ピースセットで決めたずらし方を順に試し、どこにもずらせない場合は `None` を返す
+fn super_rotation(field: &Field, pos: &Position, block: &BlockShape) -> Option<Position> {
+    pieces::current()
+        .kicks_of(block)
+        .iter()
+        .filter_map(|&(dx, dy)| {
+            Some(Position {
+                x: pos.x.checked_add_signed(dx)?,
+                y: pos.y.checked_add_signed(dy)?,
+            })
+        })
+        .find(|pos| !is_collision(field, pos, block))
+}
+
+/// ネクストキューが表示する数より少なければバッファから補充する
+/// ネクストが決められている場合は補充しない
+pub fn fill_next(game: &mut Game) {
+    if game.fixed_queue {
+        return;
+    }
+    while game.next.len() < game.rules.preview.max(1) {
+        if game.next_buf.is_empty() {
+            game.next_buf = gen_bag(&mut game.rng).into();
         }
+        game.next.push_back(game.next_buf.pop_front().unwrap());
     }
-    Err(())
 }
 
-/// ブロックを生成する
-/// 生成に失敗した場合はエラーを返す
-pub fn spawn_block(game: &mut Game) -> Result<(), ()> {
-    game.pos = Position::init();
-    game.block = game.next.pop_front().unwrap();
-    if let Some(next) = game.next_buf.pop_front() {
-        // バフからネクストキューに追加
-        game.next.push_back(next);
-    } else {
-        // バフを生成
-        game.next_buf = gen_block_7().into();
-        // バフからネクストキューに追加
-        game.next.push_back(game.next_buf.pop_front().unwrap());
+/// ゲームオーバーのイベントを溜めてエラーを返す
+fn top_out<T>(game: &mut Game, reason: GameOverReason) -> Result<T, GameOverReason> {
+    game.emit(Event::GameOver { reason });
+    Err(reason)
+}
+
+/// ブロックが出てくる位置(ピースセットで決めた分だけずらす)
+pub fn spawn_pos(block: &BlockShape) -> Position {
+    let init = Position::init();
+    let (dx, dy) = pieces::current()
+        .of(block)
+        .map_or((0, 0), |piece| piece.spawn);
+    Position {
+        x: init.x.saturating_add_signed(dx),
+        y: init.y.saturating_add_signed(dy),
+    }
+}
+
+/// 回転法則に合わせた、出てきたときの向きの形
+pub fn spawn_shape(rules: &Rules, block: &BlockShape) -> BlockShape {
+    match (rules.rotation, BlockKind::from_shape(block)) {
+        (RotationSystem::Arika, Some(kind)) => arika::shape(kind, 0),
+        _ => *block,
     }
+}
+
+/// ブロックを生成する
+/// 生成に失敗した場合や、決められたネクストを使い切った場合はエラーを返す
+pub fn spawn_block(game: &mut Game) -> Result<(), GameOverReason> {
+    game.rotated = false;
+    let Some(block) = game.next.pop_front() else {
+        game.block = BlockShape::default();
+        game.pos = Position::init();
+        return top_out(game, GameOverReason::OutOfPieces);
+    };
+    game.block = spawn_shape(&game.rules, &block);
+    game.pos = spawn_pos(&game.block);
+    // バフからネクストキューに補充
+    fill_next(game);
     if is_collision(&game.field, &game.pos, &game.block) {
-        Err(())
+        top_out(game, GameOverReason::BlockOut)
     } else {
+        game.emit(Event::PieceSpawned {
+            kind: BlockKind::from_shape(&game.block),
+        });
         Ok(())
     }
 }
 
+/// Tスピンか判定する
+/// Tブロックが回転で入り、中心の四隅のうち3つ以上が埋まっていればTスピン
+pub fn is_tspin(game: &Game) -> bool {
+    if !game.rotated || BlockKind::from_shape(&game.block) != Some(BlockKind::T) {
+        return false;
+    }
+    // 上下左右のうち3つがTブロックのセルが中心
+    let filled = |x: usize, y: usize| {
+        x < BLOCK_SIZE && y < BLOCK_SIZE && game.block[y][x] != block_kind::NONE
+    };
+    let Some((cx, cy)) = (0..(BLOCK_SIZE * BLOCK_SIZE))
+        .map(|i| (i % BLOCK_SIZE, i / BLOCK_SIZE))
+        .find(|&(x, y)| {
+            filled(x, y)
+                && [
+                    (x + 1, y),
+                    (x.wrapping_sub(1), y),
+                    (x, y + 1),
+                    (x, y.wrapping_sub(1)),
+                ]
+                .iter()
+                .filter(|&&(x, y)| filled(x, y))
+                .count()
+                    == 3
+        })
+    else {
+        return false;
+    };
+    let (x, y) = (game.pos.x + cx, game.pos.y + cy);
+    let bits = game.field.bits();
+    [
+        (x - 1, y - 1),
+        (x + 1, y - 1),
+        (x - 1, y + 1),
+        (x + 1, y + 1),
+    ]
+    .iter()
+    .filter(|&&(x, y)| bits[y] & (1 << x) != 0)
+    .count()
+        >= 3
+}
+
+/// ブロックを着地位置まで落とす(固定はしない)
 pub fn hard_drop(game: &mut Game) {
-    loop {
-        let new_pos = Position {
-            x: game.pos.x,
-            y: game.pos.y + 1,
-        };
-        if is_collision(&game.field, &new_pos, &game.block) {
-            break;
-        } else {
-            game.pos = new_pos;
+    game.pos = get_ghost_pos(&game.field, &game.pos, &game.block);
+}
+
+/// ブロックを固定してラインを消し、スコアとコンボを更新する
+/// return: 消したライン数
+pub fn lock_block(game: &mut Game) -> usize {
+    let tspin = is_tspin(game);
+    let kind = BlockKind::from_shape(&game.block);
+    fix_block(game);
+    game.emit(Event::PieceLocked {
+        kind,
+        pos: game.pos,
+    });
+    if game.rules.has(Modifier::Invisible) {
+        record_lock_times(game);
+    }
+    let level = game.level();
+    let line = game.field.erase_lines();
+    game.score += SCORE_TABLE[line.min(4)];
+    game.line += line;
+    if line > 0 {
+        game.combo += 1;
+    } else {
+        game.combo = 0;
+    }
+    let perfect_clear = line > 0 && game.field.is_empty();
+    let b2b = line > 0 && (tspin || line >= 4) && game.stats.b2b;
+    game.stats
+        .record(kind, line, tspin, perfect_clear, game.combo);
+    if line > 0 {
+        game.emit(Event::LinesCleared {
+            count: line,
+            spin: tspin,
+            b2b,
+            combo: game.combo,
+            perfect_clear,
+        });
+    }
+    if game.level() > level {
+        game.emit(Event::LevelUp {
+            level: game.level(),
+        });
+    }
+    line
+}
+
+/// 固定したブロックのセルに時刻を記録し、消えるラインに合わせて時刻をずらす
+/// ラインを消す前に呼ぶ
+#[allow(clippy::needless_range_loop)]
+fn record_lock_times(game: &mut Game) {
+    let now = Instant::now();
+    for y in 0..BLOCK_SIZE {
+        for x in 0..BLOCK_SIZE {
+            if game.block[y][x] != block_kind::NONE {
+                game.lock_times[y + game.pos.y][x + game.pos.x] = Some(now);
+            }
         }
     }
-    move_block(game, game.pos);
+    let bits = game.field.bits();
+    for y in 0..(FIELD_HEIGHT - 2) {
+        if bits[y] & FULL_ROW == FULL_ROW {
+            game.lock_times.copy_within(0..y, 1);
+            game.lock_times[0] = [None; FIELD_WIDTH];
+        }
+    }
+}
+
+/// お邪魔ブロックを下から `lines` 段せり上げる
+/// `hole` は穴を空ける列(フィールドの左端を0とする)
+/// ブロックがフィールドの上にはみ出した場合はエラーを返す
+pub fn receive_garbage(game: &mut Game, lines: usize, hole: usize) -> Result<(), GameOverReason> {
+    // 床と番兵を除いた見える範囲の行数
+    let height = FIELD_HEIGHT - 2;
+    let lines = lines.min(height);
+    if lines == 0 {
+        return Ok(());
+    }
+    let colors = *game.field.colors();
+    // 壁になっている列を除いた列
+    let columns = game.field.play_columns();
+    let hole = columns[hole % columns.len()];
+    // 押し出される行にブロックがあればはみ出す
+    let overflow = colors[..lines]
+        .iter()
+        .any(|row| columns.iter().any(|&x| row[x] != block_kind::NONE));
+    for y in 0..height {
+        for &x in &columns {
+            let color = if y + lines < height {
+                colors[y + lines][x]
+            } else if x == hole {
+                block_kind::NONE
+            } else {
+                block_kind::GARBAGE
+            };
+            game.field.set(x, y, color);
+        }
+    }
+    game.emit(Event::GarbageReceived { lines });
+    // 操作中のブロックが重なったら上へずらす
+    while is_collision(&game.field, &game.pos, &game.block) && game.pos.y > 0 {
+        game.pos.y -= 1;
+    }
+    if overflow || is_collision(&game.field, &game.pos, &game.block) {
+        return top_out(game, GameOverReason::GarbageTopOut);
+    }
+    Ok(())
+}
+
+/// ブロックが全て出てくる位置より上にあるか
+/// このフィールドには見えない範囲がないので、ブロックが出てきたときに
+/// 一番上のセルがある行より上を見えない範囲の代わりとする
+fn is_lock_out(rules: &Rules, pos: &Position, block: &BlockShape) -> bool {
+    let Some(piece) = pieces::current().of(block) else {
+        return false;
+    };
+    let spawn = spawn_shape(rules, &piece.shape);
+    let Some(top) = (0..BLOCK_SIZE).find(|&y| spawn[y].iter().any(|&c| c != block_kind::NONE))
+    else {
+        return false;
+    };
+    let top = spawn_pos(&spawn).y + top;
+    (0..BLOCK_SIZE)
+        .filter(|&y| block[y].iter().any(|&c| c != block_kind::NONE))
+        .all(|y| pos.y + y < top)
+}
+
+/// ブロックを固定し、ゲームの変化があれば反映する(次のブロックは出さない)
+/// 出てくる位置より上で固定した場合はエラーを返す
+/// return: 消したライン数
+pub fn lock_piece(game: &mut Game) -> Result<usize, GameOverReason> {
+    let lock_out = is_lock_out(&game.rules, &game.pos, &game.block);
+    let line = lock_block(game);
+    if lock_out {
+        return top_out(game, GameOverReason::LockOut);
+    }
+    if game.rules.has(Modifier::Mirror) && game.stats.pieces.is_multiple_of(MIRROR_INTERVAL) {
+        let columns = game.field.play_columns();
+        game.field.mirror();
+        for row in game.lock_times.iter_mut() {
+            let times = *row;
+            for (&x, &from) in columns.iter().zip(columns.iter().rev()) {
+                row[x] = times[from];
+            }
+        }
+    }
+    Ok(line)
 }
 
 /// ブロックが着地したときの処理
-pub fn landing(game: &mut Game) -> Result<(), ()> {
-    fix_block(game);
-    let line = erace_line(&mut game.field);
-    game.score += SCORE_TABLE[line];
-    game.line += line;
+/// ブロックを固定できても、次のブロックを出せなければエラーを返す
+pub fn landing(game: &mut Game) -> Result<(), GameOverReason> {
+    lock_piece(game)?;
     spawn_block(game)?;
     game.holded = false;
     Ok(())
 }
 
-/// 盤面を描画し、ゲームオーバーを表示し、プログラムを終了する
-pub fn gameover(game: &Game) -> ! {
-    draw(game);
-    println!("Game Over!");
-    quit();
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一番右の列を空けて、`rows` の行をお邪魔ブロックで埋める(ラインは消えない)
+    fn fill_rows(game: &mut Game, rows: std::ops::Range<usize>) {
+        for y in rows {
+            for x in 2..(FIELD_WIDTH - 3) {
+                game.field.set(x, y, block_kind::GARBAGE);
+            }
+        }
+    }
+
+    /// 最後に溜まったゲームオーバーのイベントの理由
+    fn game_over_event(game: &mut Game) -> Option<GameOverReason> {
+        game.drain_events()
+            .filter_map(|event| match event {
+                Event::GameOver { reason } => Some(reason),
+                _ => None,
+            })
+            .last()
+    }
 
-/// カーソルを表示し、プログラムを終了する
-pub fn quit() -> ! {
-    println!("\x1b[?25h"); // カーソルを表示
-    std::process::exit(0);
+    #[test]
+    fn block_out() {
+        let mut game = Game::with_seed(0);
+        hard_drop(&mut game);
+        fill_rows(&mut game, 1..3);
+        assert_eq!(landing(&mut game), Err(GameOverReason::BlockOut));
+        assert_eq!(game_over_event(&mut game), Some(GameOverReason::BlockOut));
+    }
+
+    #[test]
+    fn lock_out() {
+        // 逆さまにしたIブロックは出てきたときより1行上にある
+        let mut game = Game::with_seed(0);
+        game.block = BlockKind::I.shape();
+        game.pos = spawn_pos(&game.block);
+        rotate_right(&mut game);
+        rotate_right(&mut game);
+        fill_rows(&mut game, 2..(FIELD_HEIGHT - 2));
+        assert_eq!(landing(&mut game), Err(GameOverReason::LockOut));
+        assert_eq!(game_over_event(&mut game), Some(GameOverReason::LockOut));
+    }
+
+    #[test]
+    fn lock_in_spawn_rows_is_not_lock_out() {
+        let mut game = Game::with_seed(0);
+        game.block = BlockKind::T.shape();
+        game.pos = spawn_pos(&game.block);
+        fill_rows(&mut game, 3..(FIELD_HEIGHT - 2));
+        assert_eq!(lock_piece(&mut game), Ok(0));
+    }
+
+    #[test]
+    fn receive_garbage_events() {
+        let mut game = Game::with_seed(0);
+        game.drain_events().count();
+        assert_eq!(receive_garbage(&mut game, 2, 3), Ok(()));
+        assert_eq!(game.field[FIELD_HEIGHT - 3][2], block_kind::GARBAGE);
+        assert_eq!(game.field[FIELD_HEIGHT - 3][5], block_kind::NONE);
+        game.field.set(2, 0, block_kind::GARBAGE);
+        assert_eq!(
+            receive_garbage(&mut game, 1, 0),
+            Err(GameOverReason::GarbageTopOut)
+        );
+        let events: Vec<_> = game.drain_events().collect();
+        assert_eq!(
+            events,
+            [
+                Event::GarbageReceived { lines: 2 },
+                Event::GarbageReceived { lines: 1 },
+                Event::GameOver {
+                    reason: GameOverReason::GarbageTopOut
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn garbage_top_out() {
+        let mut game = Game::with_seed(0);
+        assert_eq!(receive_garbage(&mut game, 2, 0), Ok(()));
+        game.field.set(2, 1, block_kind::GARBAGE);
+        assert_eq!(
+            receive_garbage(&mut game, 2, 0),
+            Err(GameOverReason::GarbageTopOut)
+        );
+        assert_eq!(
+            game_over_event(&mut game),
+            Some(GameOverReason::GarbageTopOut)
+        );
+    }
 }
@@ -0,0 +1,55 @@
+//! 練習用の巻き戻し
+
+use std::collections::VecDeque;
+
+use crate::{
+    game::{spawn_pos, spawn_shape, Game},
+    pieces,
+};
+
+/// 巻き戻せる手数のデフォルト
+pub const DEFAULT_LIMIT: usize = 50;
+
+/// 着地するたびに保存したゲームの履歴
+/// 古いものから捨て、最大 `limit` 手まで巻き戻せる
+pub struct History {
+    snapshots: VecDeque<Game>,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            limit,
+        }
+    }
+
+    /// 着地する直前のゲームを保存する
+    pub fn push(&mut self, game: &Game) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.limit {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(game.clone());
+    }
+
+    /// 履歴を全て捨てる
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    /// 最後に置いたブロックを置く前に戻す
+    /// ブロックは出てきたときの位置と向きに戻る
+    pub fn undo(&mut self) -> Option<Game> {
+        let mut game = self.snapshots.pop_back()?;
+        if let Some(piece) = pieces::current().of(&game.block) {
+            game.block = spawn_shape(&game.rules, &piece.shape);
+        }
+        game.pos = spawn_pos(&game.block);
+        game.rotated = false;
+        Some(game)
+    }
+}
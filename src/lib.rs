@@ -0,0 +1,52 @@
+//! テトリスのエンジン
+//!
+//! 盤面やブロックの操作、ルール、AIなどのゲームの処理をまとめたライブラリ
+//! 端末への描画や入力は行わないので、ツールなどから使える
+//!
+//! ```
+//! use rust_tetris_tutorial::{ai, game::{self, Game}};
+//!
+//! // 同じシード値なら同じ順番でブロックが出てくる
+//! let mut game = Game::with_seed(0);
+//! for _ in 0..10 {
+//!     game = ai::eval(&game);
+//!     game::landing(&mut game).unwrap();
+//! }
+//! assert_eq!(game.stats.pieces, 10);
+//! ```
+//!
+//! - [`game`] ゲームの状態と、移動、回転、ホールド、着地などの操作
+//! - [`block`] ブロックの形とブロックの生成(7種1巡)
+//! - [`pieces`] ブロックの種類の定義(テトリミノ、ペントミノなどのピースセット)
+//! - [`field`] フィールドとビットボード
+//! - [`event`] ブロックの固定やライン消去などのイベント
+//! - [`rules`] ネクストの表示数、ホールドのルールとゲームの変化
+//! - [`arika`] アリカ式の回転法則
+//! - [`master`] 20Gのマスターモードの進行と段位
+//! - [`ai`] 置き場所を評価するAI
+//! - [`stats`] 統計と火力の計算
+//! - [`board`], [`fumen`], [`puzzle`] 盤面、テト譜、パズルの読み書き
+//! - [`history`], [`finesse`] 巻き戻しと最短の操作の探索
+//! - [`save`], [`scores`] ゲームの保存とハイスコア
+//! - [`tbp`] Tetris Bot Protocol
+//! - [`bench`] AIのベンチマーク
+
+pub mod ai;
+pub mod arika;
+pub mod bench;
+pub mod block;
+pub mod board;
+pub mod event;
+pub mod field;
+pub mod finesse;
+pub mod fumen;
+pub mod game;
+pub mod history;
+pub mod master;
+pub mod pieces;
+pub mod puzzle;
+pub mod rules;
+pub mod save;
+pub mod scores;
+pub mod stats;
+pub mod tbp;
@@ -1,32 +1,294 @@
-mod ai;
-mod block;
-mod game;
 mod play;
+mod render;
+mod spectate;
+mod term;
+mod theme;
+
+use std::{io, path::PathBuf};
 
 use clap::{Parser, Subcommand};
+use rust_tetris_tutorial::{
+    bench,
+    board::Board,
+    fumen::{self, Page},
+    game::Game,
+    history::{self, History},
+    pieces::{self, PieceSet},
+    puzzle::Puzzle,
+    rules::{self, HoldRule, Modifier, RotationSystem, Rules},
+    save,
+    scores::{self, Scores},
+    tbp,
+};
+use spectate::Address;
+use theme::{ColorDepth, Theme};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     mode: Option<Mode>,
+    /// 指定した盤面ファイルから始める
+    #[arg(long, global = true)]
+    board: Option<PathBuf>,
+    /// 指定したテト譜から始める
+    #[arg(long, global = true, conflicts_with = "board")]
+    fumen: Option<String>,
+    /// テト譜のページ番号(1から)
+    #[arg(long, global = true)]
+    page: Option<usize>,
+    /// ネクストの表示数
+    #[arg(long, global = true, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=rules::MAX_PREVIEW as i64))]
+    preview: u8,
+    /// ホールドのルール
+    #[arg(long, global = true, value_enum, default_value_t = HoldRule::Once)]
+    hold: HoldRule,
+    /// 回転法則
+    #[arg(long, global = true, value_enum, default_value_t = RotationSystem::Super)]
+    rotation: RotationSystem,
+    /// ゲームの変化(カンマ区切りで重ねられる)
+    #[arg(long = "modifier", global = true, value_enum, value_delimiter = ',')]
+    modifiers: Vec<Modifier>,
+    /// テーマ(組み込みのテーマの名前かテーマファイルのパス)
+    #[arg(long, global = true, default_value = "default")]
+    theme: String,
+    /// ピースセット(組み込みのセットの名前かピースセットファイルのパス)
+    #[arg(long, global = true, default_value = "tetromino")]
+    pieces: String,
+    /// 色数(autoの場合は環境変数から判定する)
+    #[arg(long, global = true, value_enum, default_value_t = ColorDepth::Auto)]
+    color: ColorDepth,
+    /// 観戦用にゲームの状態を配信するアドレス(host:port か unix:path)
+    #[arg(long, global = true)]
+    publish: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Mode {
     Normal,
+    /// 巻き戻しができる練習モード(ハイスコアは記録しない)
+    Practice {
+        /// 巻き戻せる手数
+        #[arg(long, default_value_t = history::DEFAULT_LIMIT)]
+        undo_limit: usize,
+    },
+    /// 操作の無駄を指摘する練習モード
+    Finesse,
+    /// 20Gのマスターモード(段位を目指す)
+    Master,
     Auto,
+    /// 保存したゲームを再開する
+    Resume {
+        /// 保存ファイル(省略するとデータディレクトリの save.json)
+        file: Option<PathBuf>,
+    },
+    /// 外部のTBPボットにプレイさせる
+    ExternalBot {
+        /// ボットのプログラム
+        program: String,
+        /// プログラムに渡す引数(`--` の後に書く)
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// 標準入出力でTBPのボットとして動作する
+    Bot,
+    /// 配信されているゲームを観戦する
+    Watch {
+        /// 配信しているアドレス(host:port か unix:path)
+        addr: String,
+    },
+    /// 画面に描画せずにAIを複数ゲームプレイさせ、結果を集計する
+    Bench {
+        /// ゲーム数(シード値を指定しない場合は0から順に使う)
+        #[arg(short = 'n', long, default_value_t = 10)]
+        games: u64,
+        /// シード値のリスト(カンマ区切り)
+        #[arg(long, value_delimiter = ',')]
+        seeds: Vec<u64>,
+        /// 1ゲームあたりの最大ブロック数
+        #[arg(long, default_value_t = 10000)]
+        max_pieces: usize,
+        /// JSONで出力する
+        #[arg(long)]
+        json: bool,
+    },
+    /// パズルを解く
+    Puzzle {
+        /// パズルのファイル
+        file: PathBuf,
+    },
+    /// ハイスコアを表示する
+    Scores {
+        /// モードで絞り込む
+        #[arg(long)]
+        mode: Option<String>,
+        /// ルールで絞り込む
+        #[arg(long)]
+        ruleset: Option<String>,
+        /// 表示する件数
+        #[arg(short = 'n', long, default_value_t = scores::MAX_ENTRIES)]
+        limit: usize,
+    },
+    /// テト譜を盤面のテキスト形式に変換する
+    ImportFumen {
+        /// テト譜のデータまたはURL
+        fumen: String,
+    },
+    /// 盤面ファイルをテト譜に変換する(1ファイル1ページ)
+    ExportFumen {
+        /// 盤面ファイル
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
+    let theme = Theme::load(&cli.theme).unwrap_or_else(|err| exit_with(&err));
+    theme::init(&theme, cli.color);
+    let pieces = PieceSet::load(&cli.pieces).unwrap_or_else(|err| exit_with(&err));
+    let standard = pieces.is_standard();
+    pieces::init(pieces).unwrap_or_else(|err| exit_with(&err));
+    let board = match (&cli.board, &cli.fumen) {
+        (Some(path), _) => Some(Board::load(path).unwrap_or_else(|err| exit_with(&err))),
+        (None, Some(fumen)) => {
+            let pages = fumen::decode(fumen).unwrap_or_else(|err| exit_with(&err));
+            let index = cli.page.unwrap_or(1).saturating_sub(1);
+            Some(Page::board(&pages, index).unwrap_or_else(|| exit_with("no such page")))
+        }
+        (None, None) => None,
+    };
+    let rules = Rules {
+        preview: cli.preview as usize,
+        hold: cli.hold,
+        rotation: cli.rotation,
+        modifiers: cli.modifiers.into_iter().collect(),
+    };
+    if rules.has(Modifier::Big) && board.is_some() {
+        exit_with("the `big` modifier cannot start from a board");
+    }
+    if !standard && board.is_some() {
+        exit_with("a board can only be used with the tetromino piece set");
+    }
+    if !standard && rules.rotation == RotationSystem::Arika {
+        exit_with("arika rotation can only be used with the tetromino piece set");
+    }
+    if let Some(addr) = &cli.publish {
+        spectate::publish_on(&Address::parse(addr)).unwrap_or_else(|err| exit_with(&err));
+    }
+    let new_game = || {
+        let mut game = Game::with_rules(rand::random(), rules);
+        if let Some(board) = &board {
+            board.apply(&mut game);
+        }
+        game
+    };
 
     match cli.mode {
         None | Some(Mode::Normal) => {
-            play::normal();
+            play::normal(new_game(), None);
+        }
+        Some(Mode::Practice { undo_limit }) => {
+            play::normal(new_game(), Some(History::new(undo_limit)));
+        }
+        Some(Mode::Resume { file }) => {
+            let path = file.unwrap_or_else(save::default_path);
+            let game = save::resume(&path).unwrap_or_else(|err| exit_with(&err));
+            play::normal(game, None);
+        }
+        Some(Mode::Finesse | Mode::ExternalBot { .. } | Mode::Bot | Mode::Puzzle { .. })
+            if !standard =>
+        {
+            exit_with("this mode can only be used with the tetromino piece set");
+        }
+        Some(Mode::Finesse) => {
+            play::finesse(new_game());
+        }
+        Some(Mode::Master) => {
+            play::master(new_game());
         }
         Some(Mode::Auto) => {
-            play::auto();
+            play::auto(new_game());
+        }
+        Some(Mode::ExternalBot { .. }) if rules.has(Modifier::Big) => {
+            exit_with("the `big` modifier cannot be used with an external bot");
+        }
+        Some(Mode::ExternalBot { program, args }) => {
+            play::external_bot(&program, &args, new_game());
+        }
+        Some(Mode::Puzzle { .. }) if rules.has(Modifier::Big) => {
+            exit_with("the `big` modifier cannot be used with puzzles");
+        }
+        Some(Mode::Puzzle { file }) => {
+            let puzzles = Puzzle::load(&file).unwrap_or_else(|err| exit_with(&err));
+            play::puzzle(puzzles, rules);
+        }
+        Some(Mode::Watch { addr }) => {
+            spectate::watch(&Address::parse(&addr));
+        }
+        Some(Mode::Bot) => {
+            if let Err(err) = tbp::bot(io::stdin().lock(), io::stdout()) {
+                exit_with(&err.to_string());
+            }
+        }
+        Some(Mode::Bench {
+            games,
+            mut seeds,
+            max_pieces,
+            json,
+        }) => {
+            if seeds.is_empty() {
+                seeds = (0..games).collect();
+            }
+            let report = bench::run(&seeds, max_pieces, board.as_ref(), rules);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                print!("{report}");
+            }
+        }
+        Some(Mode::Scores {
+            mode,
+            ruleset,
+            limit,
+        }) => {
+            let scores = Scores::load().unwrap_or_else(|err| exit_with(&err));
+            print!(
+                "{}",
+                scores.format_table(mode.as_deref(), ruleset.as_deref(), limit)
+            );
+        }
+        Some(Mode::ImportFumen { fumen }) => {
+            let pages = fumen::decode(&fumen).unwrap_or_else(|err| exit_with(&err));
+            let indices = match cli.page {
+                Some(page) => vec![page.saturating_sub(1)],
+                None => (0..pages.len()).collect(),
+            };
+            for index in indices {
+                let board = Page::board(&pages, index).unwrap_or_else(|| exit_with("no such page"));
+                println!("; page {}", index + 1);
+                for line in pages[index].comment.lines() {
+                    println!("; {line}");
+                }
+                println!("{}", board.to_text());
+            }
+        }
+        Some(Mode::ExportFumen { files }) => {
+            let pages: Vec<Page> = files
+                .iter()
+                .map(|path| {
+                    let board = Board::load(path).unwrap_or_else(|err| exit_with(&err));
+                    Page::try_from(&board)
+                        .unwrap_or_else(|err| exit_with(&format!("{}: {err}", path.display())))
+                })
+                .collect();
+            println!("{}", fumen::encode(&pages));
         }
     }
 }
+
+/// エラーを表示して終了する
+fn exit_with(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
@@ -0,0 +1,448 @@
+//! 20Gのマスターモード
+//!
+//! レベル999を目指すモード。レベルはブロックが出てくるたびとラインを消したときに上がり、
+//! 落下速度は最後には出てきた瞬間に積まれている所まで落ちる速さ(20G)になる
+//! ブロックが出てくるまでの待ち時間(ARE)、ライン消去の待ち時間、横移動の溜め(DAS)、
+//! 固定までの猶予はセクション(100レベル)ごとに短くなる
+//!
+//! 消したラインで内部の段位ポイントを貯めて段位(9からS9)を上げ、
+//! 全てのセクションを一定の時間内に終えてS9でレベル999に届けばGMになる
+//!
+//! 時間はフレーム(1/60秒)単位で、フロントエンドは毎フレーム [`Master::tick`] を呼ぶ
+
+use std::time::Duration;
+
+use crate::{
+    block::BlockShape,
+    event::Event,
+    game::{
+        hard_drop, hold, is_collision, lock_piece, move_block, rotate_left, rotate_right,
+        spawn_block, Game, GameOverReason, Position,
+    },
+};
+
+/// 1秒あたりのフレーム数
+pub const FPS: u64 = 60;
+/// 最後のレベル
+pub const MAX_LEVEL: usize = 999;
+/// 1セクションのレベル数
+pub const SECTION: usize = 100;
+/// 1Gの重力(1フレームに1行落ちる)
+const G: u32 = 256;
+
+/// レベルごとの重力(1/256G単位、レベルが左の値以上のとき)
+const GRAVITY_TABLE: [(usize, u32); 30] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (120, 96),
+    (140, 112),
+    (160, 128),
+    (170, 144),
+    (200, 4),
+    (220, 32),
+    (230, 64),
+    (233, 96),
+    (236, 128),
+    (239, 160),
+    (243, 192),
+    (247, 224),
+    (251, 256),
+    (300, 512),
+    (330, 768),
+    (360, 1024),
+    (400, 1280),
+    (420, 1024),
+    (450, 768),
+    (500, 20 * G),
+];
+
+/// 待ち時間(フレーム)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delays {
+    pub are: u32,        // ブロックが出てくるまで
+    pub line_are: u32,   // ラインを消した後、ブロックが出てくるまで
+    pub das: u32,        // 横移動を押し続けてから連続で動き始めるまで
+    pub lock: u32,       // 接地してから固定するまで
+    pub line_clear: u32, // ラインを消している間
+}
+
+/// セクションごとの待ち時間(レベルが左の値以上のとき)
+const DELAY_TABLE: [(usize, Delays); 6] = [
+    (0, Delays::new(25, 25, 14, 30, 40)),
+    (500, Delays::new(25, 25, 8, 30, 25)),
+    (600, Delays::new(25, 16, 8, 30, 16)),
+    (700, Delays::new(16, 12, 8, 30, 12)),
+    (800, Delays::new(12, 6, 8, 30, 6)),
+    (900, Delays::new(12, 6, 6, 17, 6)),
+];
+
+impl Delays {
+    const fn new(are: u32, line_are: u32, das: u32, lock: u32, line_clear: u32) -> Self {
+        Self {
+            are,
+            line_are,
+            das,
+            lock,
+            line_clear,
+        }
+    }
+}
+
+/// 段位の名前(弱い順)
+pub const GRADES: [&str; 19] = [
+    "9", "8", "7", "6", "5", "4", "3", "2", "1", "S1", "S2", "S3", "S4", "S5", "S6", "S7", "S8",
+    "S9", "GM",
+];
+/// GMの段位の番号
+const GM: usize = GRADES.len() - 1;
+/// 内部段位ごとの表示する段位の番号
+const DISPLAYED_GRADE: [usize; 31] = [
+    0, 1, 2, 3, 4, 5, 5, 6, 6, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 11, 12, 12, 12, 13, 13, 14, 14, 15,
+    15, 16, 17,
+];
+/// 内部段位ごとの、ポイントが1減るまでのフレーム数と、1~4段消しで貯まるポイント
+const GRADE_POINTS: [(u32, [u32; 4]); 31] = [
+    (125, [10, 20, 40, 50]),
+    (80, [10, 20, 30, 40]),
+    (80, [10, 20, 30, 40]),
+    (50, [10, 15, 30, 40]),
+    (45, [10, 15, 20, 40]),
+    (45, [5, 15, 20, 30]),
+    (45, [5, 10, 20, 30]),
+    (40, [5, 10, 15, 30]),
+    (40, [5, 10, 15, 30]),
+    (40, [5, 10, 15, 30]),
+    (40, [2, 12, 13, 30]),
+    (35, [2, 12, 13, 30]),
+    (35, [2, 12, 13, 30]),
+    (35, [2, 12, 13, 30]),
+    (35, [2, 12, 13, 30]),
+    (30, [2, 12, 13, 30]),
+    (30, [2, 12, 13, 30]),
+    (30, [2, 12, 13, 30]),
+    (30, [2, 12, 13, 30]),
+    (30, [2, 12, 13, 30]),
+    (20, [2, 12, 13, 30]),
+    (20, [2, 12, 13, 30]),
+    (20, [2, 12, 13, 30]),
+    (20, [2, 12, 13, 30]),
+    (20, [2, 12, 13, 30]),
+    (20, [2, 12, 13, 30]),
+    (15, [2, 12, 13, 30]),
+    (15, [2, 12, 13, 30]),
+    (15, [2, 12, 13, 30]),
+    (15, [2, 12, 13, 30]),
+    (10, [2, 12, 13, 30]),
+];
+/// GMになるための1セクションの時間の上限(フレーム)
+const GM_SECTION_LIMIT: u64 = 65 * FPS;
+
+/// レベルの重力
+pub fn gravity(level: usize) -> u32 {
+    GRAVITY_TABLE
+        .iter()
+        .rev()
+        .find(|&&(from, _)| level >= from)
+        .map_or(GRAVITY_TABLE[0].1, |&(_, gravity)| gravity)
+}
+
+/// レベルの待ち時間
+pub fn delays(level: usize) -> Delays {
+    DELAY_TABLE
+        .iter()
+        .rev()
+        .find(|&&(from, _)| level >= from)
+        .map_or(DELAY_TABLE[0].1, |&(_, delays)| delays)
+}
+
+/// フレーム数を時間にする
+pub fn frames_to_duration(frames: u64) -> Duration {
+    Duration::from_micros(frames * 1_000_000 / FPS)
+}
+
+/// 1フレーム分の入力(押されているか)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub down: bool, // ソフトドロップ(接地していればすぐ固定する)
+    pub drop: bool, // ハードドロップ
+    pub rotate_right: bool,
+    pub rotate_left: bool,
+    pub hold: bool,
+}
+
+/// 今の段階
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// ブロックを操作している
+    Active,
+    /// ラインを消している(残りフレーム数)
+    LineClear(u32),
+    /// 次のブロックを待っている(残りフレーム数)
+    Are(u32),
+    /// レベル999に届いた
+    Cleared,
+}
+
+/// マスターモードの進行状況
+#[derive(Debug, Clone)]
+pub struct Master {
+    pub level: usize,
+    pub frames: u64,             // 経過フレーム数
+    pub section_times: Vec<u64>, // 終えたセクションごとのフレーム数
+    pub phase: Phase,
+    pub internal_grade: usize, // 内部段位(0~30)
+    pub grade_points: u32,     // 内部段位のポイント(100で次の内部段位)
+    gm: bool,                  // GMになったか
+    decay: u32,                // ポイントが減るまでのフレーム数
+    gravity: u32,              // 溜まった重力
+    lock: u32,                 // 接地してからのフレーム数
+    das: u32,                  // 横移動を押し続けたフレーム数
+    prev: Input,               // 前のフレームの入力
+}
+
+impl Default for Master {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Master {
+    pub fn new() -> Self {
+        Self {
+            level: 0,
+            frames: 0,
+            section_times: vec![],
+            phase: Phase::Are(delays(0).are),
+            internal_grade: 0,
+            grade_points: 0,
+            gm: false,
+            decay: 0,
+            gravity: 0,
+            lock: 0,
+            das: 0,
+            prev: Input::default(),
+        }
+    }
+
+    /// 始める(最初のブロックは待ち時間の後に出てくるので隠しておく)
+    pub fn start(&mut self, game: &mut Game) {
+        game.next.push_front(game.block);
+        game.block = BlockShape::default();
+    }
+
+    /// 今の段位
+    pub fn grade(&self) -> &'static str {
+        if self.gm {
+            GRADES[GM]
+        } else {
+            GRADES[DISPLAYED_GRADE[self.internal_grade]]
+        }
+    }
+
+    /// 今のセクションの経過フレーム数
+    pub fn section_frames(&self) -> u64 {
+        self.frames - self.section_times.iter().sum::<u64>()
+    }
+
+    /// 1フレーム進める
+    pub fn tick(&mut self, game: &mut Game, input: Input) -> Result<(), GameOverReason> {
+        if self.phase == Phase::Cleared {
+            return Ok(());
+        }
+        self.frames += 1;
+
+        // 横移動の溜めは待ち時間の間も貯まる
+        if input.left != input.right
+            && (input.left, input.right) == (self.prev.left, self.prev.right)
+        {
+            self.das += 1;
+        } else {
+            self.das = 0;
+        }
+
+        let result = match self.phase {
+            Phase::Active => {
+                if game.combo == 0 {
+                    self.decay_points();
+                }
+                self.active(game, input)
+            }
+            Phase::LineClear(0) => {
+                self.phase = Phase::Are(delays(self.level).line_are);
+                Ok(())
+            }
+            Phase::LineClear(frames) => {
+                self.phase = Phase::LineClear(frames - 1);
+                Ok(())
+            }
+            Phase::Are(0) => self.spawn(game, input),
+            Phase::Are(frames) => {
+                self.phase = Phase::Are(frames - 1);
+                Ok(())
+            }
+            Phase::Cleared => Ok(()),
+        };
+        self.prev = input;
+        result
+    }
+
+    /// 次のブロックを出し、20Gならそのまま落とす
+    fn spawn(&mut self, game: &mut Game, input: Input) -> Result<(), GameOverReason> {
+        spawn_block(game)?;
+        game.holded = false;
+        // セクションの最後のレベルはラインを消さないと上がらない
+        if self.level % SECTION != SECTION - 1 && self.level != MAX_LEVEL - 1 {
+            self.level_up(game, 1);
+        }
+        self.phase = Phase::Active;
+        self.gravity = 0;
+        self.lock = 0;
+        self.active(game, input)
+    }
+
+    /// ブロックを操作する
+    fn active(&mut self, game: &mut Game, input: Input) -> Result<(), GameOverReason> {
+        let delays = delays(self.level);
+        let pressed = |now: bool, prev: bool| now && !prev;
+
+        if pressed(input.hold, self.prev.hold) {
+            hold(game);
+        }
+        if pressed(input.rotate_right, self.prev.rotate_right) {
+            rotate_right(game);
+        }
+        if pressed(input.rotate_left, self.prev.rotate_left) {
+            rotate_left(game);
+        }
+
+        // 押した瞬間に1マス動き、押し続けると毎フレーム動く
+        let shift = match (input.left, input.right) {
+            (true, false) => Some(game.pos.x.checked_sub(1).unwrap_or(game.pos.x)),
+            (false, true) => Some(game.pos.x + 1),
+            _ => None,
+        };
+        if let Some(x) = shift {
+            if self.das == 0 || self.das >= delays.das {
+                move_block(game, Position { x, y: game.pos.y });
+            }
+        }
+
+        if pressed(input.drop, self.prev.drop) {
+            hard_drop(game);
+            return self.lock_piece(game);
+        }
+
+        // 重力で落とす(ソフトドロップは1G)
+        let mut gravity = gravity(self.level);
+        if input.down {
+            gravity = gravity.max(G);
+        }
+        self.gravity += gravity;
+        while self.gravity >= G {
+            self.gravity -= G;
+            let below = Position {
+                x: game.pos.x,
+                y: game.pos.y + 1,
+            };
+            if is_collision(&game.field, &below, &game.block) {
+                self.gravity = 0;
+                break;
+            }
+            game.pos = below;
+            // 1行落ちたら固定までの猶予を戻す
+            self.lock = 0;
+        }
+
+        // 接地していれば猶予が過ぎたら固定する
+        let below = Position {
+            x: game.pos.x,
+            y: game.pos.y + 1,
+        };
+        if is_collision(&game.field, &below, &game.block) {
+            self.lock += 1;
+            if input.down || self.lock >= delays.lock {
+                return self.lock_piece(game);
+            }
+        }
+        Ok(())
+    }
+
+    /// ブロックを固定し、ラインを消していれば段位のポイントを貯める
+    fn lock_piece(&mut self, game: &mut Game) -> Result<(), GameOverReason> {
+        let line = lock_piece(game)?;
+        // 次のブロックが出てくるまで隠す
+        game.block = BlockShape::default();
+        let delays = delays(self.level);
+        if line > 0 {
+            self.add_points(line, game.combo);
+            self.level_up(game, line);
+            self.phase = Phase::LineClear(delays.line_clear);
+        } else {
+            self.phase = Phase::Are(delays.are);
+        }
+        if self.level >= MAX_LEVEL {
+            self.finish();
+        }
+        Ok(())
+    }
+
+    /// レベルを上げ、セクションが変わったら時間を記録する
+    fn level_up(&mut self, game: &mut Game, levels: usize) {
+        let section = self.level / SECTION;
+        self.level = (self.level + levels).min(MAX_LEVEL);
+        if self.level / SECTION > section && self.level < MAX_LEVEL {
+            self.section_times.push(self.section_frames());
+            game.emit(Event::LevelUp { level: self.level });
+        }
+    }
+
+    /// レベル999に届いたので、最後のセクションの時間を記録してGMか判定する
+    fn finish(&mut self) {
+        self.section_times.push(self.section_frames());
+        self.phase = Phase::Cleared;
+        self.gm = DISPLAYED_GRADE[self.internal_grade] == GM - 1
+            && self
+                .section_times
+                .iter()
+                .all(|&frames| frames <= GM_SECTION_LIMIT);
+    }
+
+    /// 消したライン数、コンボ、レベルに応じて段位のポイントを貯める
+    fn add_points(&mut self, line: usize, combo: usize) {
+        let (_, points) = GRADE_POINTS[self.internal_grade];
+        let base = points[line.min(4) - 1];
+        // 2段消し以上のコンボで増やす(10分の1単位)
+        let combo_rate = if line >= 2 {
+            10 + 2 * (combo.saturating_sub(1).min(10) as u32)
+        } else {
+            10
+        };
+        let level_rate = 1 + self.level as u32 / 250;
+        self.grade_points += base * combo_rate / 10 * level_rate;
+        if self.grade_points >= 100 {
+            self.grade_points = 0;
+            self.decay = 0;
+            self.internal_grade = (self.internal_grade + 1).min(DISPLAYED_GRADE.len() - 1);
+        }
+    }
+
+    /// 時間が経つとポイントが減る
+    fn decay_points(&mut self) {
+        let (rate, _) = GRADE_POINTS[self.internal_grade];
+        self.decay += 1;
+        if self.decay >= rate {
+            self.decay = 0;
+            self.grade_points = self.grade_points.saturating_sub(1);
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! ブロックの種類の定義(ピースセット)
+//!
+//! ブロックの形、回転するときの大きさ、色、出てくる位置、回転で入らないときのずらし方を
+//! JSONファイルで定義する。組み込みのセットは名前で選ぶ
+//!
+//! ```json
+//! {
+//!     "name": "my-set",
+//!     "pieces": [
+//!         { "name": "T", "size": 3, "color": "T", "cells": [".#.", "###"] },
+//!         { "name": "D", "size": 2, "color": "O", "cells": ["##"], "spawn": [1, 0] }
+//!     ]
+//! }
+//! ```
+//!
+//! - `name` はブロックを表す1文字(大文字と小文字は区別する)
+//! - `size` は回転するときの正方形の大きさ(1~5)で、`cells` はこの中に `#` で書く
+//! - `color` はテーマの色の名前(`I`, `O`, `S`, `Z`, `J`, `L`, `T`, `garbage`)
+//! - `spawn` は出てくる位置を標準の位置からずらす量(右と下が正、省略すると `[0, 0]`)
+//! - `kicks` は回転で入らないときに順に試すずらし方(省略すると上、右、下、左に1マス、2マスの順)
+//! - 7種類のブロックと同じ名前で4セルのブロックは、その種類として扱う(統計やTスピンの判定)
+
+use std::{fs, path::Path, sync::OnceLock};
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::block::{block_kind, BlockColor, BlockKind, BlockShape, BLOCK_SIZE};
+
+/// 組み込みのピースセットの名前
+pub const BUILTIN_SETS: [&str; 3] = ["tetromino", "pentomino", "mini"];
+/// 1つのセットに入れられるブロックの数(観戦の配信でセルを1文字で表せる数)
+pub const MAX_PIECES: usize = 24;
+/// 形が登録されていないブロックを回転するときの大きさ
+const DEFAULT_SIZE: usize = 4;
+/// 回転で入らないときのずらし方のデフォルト(右と下が正)
+const DEFAULT_KICKS: [(isize, isize); 8] = [
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (0, -2),
+    (2, 0),
+    (0, 2),
+    (-2, 0),
+];
+/// `color` に書ける色の名前
+const COLOR_NAMES: [(&str, BlockColor); 8] = [
+    ("I", block_kind::I),
+    ("O", block_kind::O),
+    ("S", block_kind::S),
+    ("Z", block_kind::Z),
+    ("J", block_kind::J),
+    ("L", block_kind::L),
+    ("T", block_kind::T),
+    ("garbage", block_kind::GARBAGE),
+];
+
+/// ブロックの種類
+#[derive(Debug, Clone)]
+pub struct Piece {
+    pub name: char,                 // ブロックを表す文字
+    pub shape: BlockShape,          // 出てきたときの向きの形
+    pub size: usize,                // 回転するときの正方形の大きさ
+    pub color: BlockColor,          // 描画に使うテーマの色
+    pub spawn: (isize, isize),      // 出てくる位置のずれ
+    pub kicks: Vec<(isize, isize)>, // 回転で入らないときのずらし方
+}
+
+/// ゲームで使うブロックの種類の組
+#[derive(Debug, Clone)]
+pub struct PieceSet {
+    pub name: String,
+    pub pieces: Vec<Piece>,
+}
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        Self::builtin("tetromino").unwrap()
+    }
+}
+
+impl PieceSet {
+    /// 組み込みのピースセット
+    pub fn builtin(name: &str) -> Option<Self> {
+        let text = match name {
+            "tetromino" => include_str!("../pieces/tetromino.json"),
+            "pentomino" => include_str!("../pieces/pentomino.json"),
+            "mini" => include_str!("../pieces/mini.json"),
+            _ => return None,
+        };
+        Some(Self::parse(text).unwrap())
+    }
+
+    /// 組み込みのピースセットの名前か、ファイルのパスから読み込む
+    pub fn load(name: &str) -> Result<Self, String> {
+        if let Some(set) = Self::builtin(name) {
+            return Ok(set);
+        }
+        let path = Path::new(name);
+        if !path.exists() {
+            return Err(format!(
+                "unknown piece set `{name}` (built-in sets: {})",
+                BUILTIN_SETS.join(", ")
+            ));
+        }
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// JSON形式のピースセットを読み込む
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: PieceSetFile = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        if file.pieces.is_empty() || file.pieces.len() > MAX_PIECES {
+            return Err(format!("a piece set needs 1 to {MAX_PIECES} pieces"));
+        }
+        let mut pieces: Vec<Piece> = vec![];
+        for (i, piece) in file.pieces.into_iter().enumerate() {
+            let name = piece.name;
+            if pieces.iter().any(|other| other.name == name) {
+                return Err(format!("duplicate piece `{name}`"));
+            }
+            let piece = piece
+                .build(block_kind::PIECE + i)
+                .map_err(|err| format!("piece `{name}`: {err}"))?;
+            pieces.push(piece);
+        }
+        Ok(Self {
+            name: file.name,
+            pieces,
+        })
+    }
+
+    /// 7種類のブロックだけでできているか(外部のボットや盤面ファイルで使える)
+    pub fn is_standard(&self) -> bool {
+        self.pieces.len() == BlockKind::ALL.len()
+            && self
+                .pieces
+                .iter()
+                .all(|piece| BlockKind::from_shape(&piece.shape).is_some())
+    }
+
+    /// セルの色からブロックの種類を求める
+    pub fn get(&self, color: BlockColor) -> Option<&Piece> {
+        self.pieces
+            .iter()
+            .find(|piece| first_cell(&piece.shape) == Some(color))
+    }
+
+    /// ブロックの形から種類を求める
+    pub fn of(&self, shape: &BlockShape) -> Option<&Piece> {
+        first_cell(shape).and_then(|color| self.get(color))
+    }
+
+    /// 文字からブロックの種類を求める
+    pub fn by_name(&self, name: char) -> Option<&Piece> {
+        self.pieces.iter().find(|piece| piece.name == name)
+    }
+
+    /// 回転するときの正方形の大きさ
+    pub fn size_of(&self, shape: &BlockShape) -> usize {
+        self.of(shape).map_or(DEFAULT_SIZE, |piece| piece.size)
+    }
+
+    /// 回転で入らないときのずらし方
+    pub fn kicks_of(&self, shape: &BlockShape) -> &[(isize, isize)] {
+        self.of(shape)
+            .map_or(&DEFAULT_KICKS[..], |piece| &piece.kicks[..])
+    }
+
+    /// セルを描画するときのテーマの色
+    pub fn palette(&self, color: BlockColor) -> BlockColor {
+        self.get(color).map_or(color, |piece| piece.color)
+    }
+
+    /// ブロックを1つ選ぶ
+    pub fn random<R: Rng + ?Sized>(&self, rng: &mut R) -> BlockShape {
+        self.pieces[rng.gen_range(0..self.pieces.len() as u32) as usize].shape
+    }
+}
+
+/// 空でない最初のセルの色
+fn first_cell(shape: &BlockShape) -> Option<BlockColor> {
+    shape
+        .iter()
+        .flatten()
+        .find(|&&cell| cell != block_kind::NONE)
+        .copied()
+}
+
+/// ピースセットのファイル
+#[derive(Deserialize)]
+struct PieceSetFile {
+    name: String,
+    pieces: Vec<PieceFile>,
+}
+
+/// ピースセットのファイルに書くブロック
+#[derive(Deserialize)]
+struct PieceFile {
+    name: char,
+    size: usize,
+    color: String,
+    cells: Vec<String>,
+    #[serde(default)]
+    spawn: (isize, isize),
+    kicks: Option<Vec<(isize, isize)>>,
+}
+
+impl PieceFile {
+    /// `id` はセットの中で使うセルの色(7種類のブロックの場合はその色にする)
+    fn build(self, id: BlockColor) -> Result<Piece, String> {
+        if !(1..=BLOCK_SIZE).contains(&self.size) {
+            return Err(format!("size must be 1 to {BLOCK_SIZE}"));
+        }
+        let color = COLOR_NAMES
+            .iter()
+            .find(|(name, _)| *name == self.color)
+            .map(|&(_, color)| color)
+            .ok_or_else(|| format!("unknown color `{}`", self.color))?;
+        let mut cells = vec![];
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '#' if x < self.size && y < self.size => cells.push((x, y)),
+                    '#' => return Err(format!("cells must fit in {0}x{0}", self.size)),
+                    '.' | ' ' => {}
+                    c => return Err(format!("invalid cell `{c}`")),
+                }
+            }
+        }
+        if cells.is_empty() {
+            return Err("no cells".into());
+        }
+        let kind = BlockKind::from_char(self.name)
+            .filter(|kind| kind.to_char() == self.name && cells.len() == 4);
+        let id = kind.map_or(id, BlockKind::color);
+        let mut shape = BlockShape::default();
+        for (x, y) in cells {
+            shape[y][x] = id;
+        }
+        Ok(Piece {
+            name: self.name,
+            shape,
+            size: self.size,
+            color,
+            spawn: self.spawn,
+            kicks: self.kicks.unwrap_or_else(|| DEFAULT_KICKS.to_vec()),
+        })
+    }
+}
+
+/// 使うピースセット
+static PIECES: OnceLock<PieceSet> = OnceLock::new();
+
+/// 使うピースセットを設定する
+/// 設定しない場合は7種類のブロックを使う
+/// 既に設定されているか、使い始めている場合はエラーを返す
+pub fn init(set: PieceSet) -> Result<(), String> {
+    PIECES
+        .set(set)
+        .map_err(|set| format!("cannot switch to the `{}` piece set", set.name))
+}
+
+/// 使っているピースセット
+pub fn current() -> &'static PieceSet {
+    PIECES.get_or_init(PieceSet::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_builtin_sets() {
+        for name in BUILTIN_SETS {
+            let set = PieceSet::load(name).unwrap();
+            assert_eq!(set.name, name);
+        }
+        assert!(PieceSet::default().is_standard());
+    }
+}
@@ -1,39 +1,87 @@
 use std::{
-    sync::{Arc, Mutex},
+    fmt, fs,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread::{self, sleep},
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use getch_rs::{Getch, Key};
-
-use crate::{
-    ai::eval,
+use getch_rs::Key;
+use rust_tetris_tutorial::{
+    ai::{eval, hint},
+    block::BlockKind,
+    board::Board,
+    event::Event,
+    field::{narrow_field, FIELD_HEIGHT, FIELD_WIDTH},
+    finesse::{minimal_inputs, placed_cells, Input},
     game::{
-        draw, gameover, hard_drop, hold, is_collision, landing, move_block, quit, rotate_left,
-        rotate_right, Game, Position,
+        hard_drop, hold, is_collision, landing, move_block, rotate_left, rotate_right, Game,
+        GameOverReason, Position,
     },
+    history::History,
+    master::{self, Master, Phase, FPS, MAX_LEVEL, SECTION},
+    puzzle::{Attempt, Puzzle, Status},
+    rules::Rules,
+    save,
+    scores::{Entry, Scores},
+    stats::format_time,
+    tbp::{apply_move, start_of, ExternalBot, FrontendMessage},
+};
+
+use crate::{
+    render::{draw, draw_with_hint, print_summary, show_gameover},
+    term::{self, Terminal},
 };
 
+/// ハイスコアの名前の最大文字数
+const MAX_NAME_LENGTH: usize = 16;
+
+/// ゲームオーバー後の状態
+struct Over {
+    reason: GameOverReason, // ゲームオーバーの理由
+    entry: Option<Entry>,   // ハイスコアの名前の入力を待っている記録
+}
+
 /// 通常プレイ
-pub fn normal() -> ! {
+/// 履歴を渡すと巻き戻しができる練習モードになり、ハイスコアは記録しない
+/// ゲームオーバーになったら理由を表示し、最初の状態からやり直せる
+pub fn normal(game: Game, history: Option<History>) -> ! {
     // ゲームの初期化
-    let game = Arc::new(Mutex::new(Game::new()));
+    let initial = game.clone();
+    let game = Arc::new(Mutex::new(game));
+    let show_hint = Arc::new(AtomicBool::new(false));
+    let history = Arc::new(Mutex::new(history));
+    // ゲームオーバーになっていれば、その理由と名前の入力を待っている記録
+    let over: Arc<Mutex<Option<Over>>> = Arc::new(Mutex::new(None));
 
-    // 画面クリア
-    println!("\x1b[2J\x1b[H\x1b[?25l");
+    // 代替画面に切り替える
+    let terminal = Terminal::enter();
 
     // 初回描画(フィールドの描画)
-    draw(&game.lock().unwrap());
+    draw_normal(&game.lock().unwrap(), &show_hint);
+
+    // 端末の大きさが変わったら描画し直す
+    {
+        let game = Arc::clone(&game);
+        let show_hint = Arc::clone(&show_hint);
+        term::watch_resize(move || draw_normal(&game.lock().unwrap(), &show_hint));
+    }
 
     // 自然落下処理
     {
         let game = Arc::clone(&game);
+        let show_hint = Arc::clone(&show_hint);
+        let history = Arc::clone(&history);
+        let over = Arc::clone(&over);
 
         thread::spawn(move || {
             loop {
                 // 10ライン消すごとに、100ミリ秒速くすることにする
                 let sleep_msec =
-                    match 1000u64.saturating_sub((game.lock().unwrap().line as u64 / 10) * 100) {
+                    match 1000u64.saturating_sub(game.lock().unwrap().level() as u64 * 100) {
                         0 => 100,
                         msec => msec,
                     };
@@ -41,6 +89,11 @@ pub fn normal() -> ! {
 
                 // 必要な変数の取得
                 let mut game = game.lock().unwrap();
+                let mut over = over.lock().unwrap();
+                if over.is_some() {
+                    // やり直すか巻き戻すまで止める
+                    continue;
+                }
 
                 // 自然落下
                 let new_pos = Position {
@@ -50,114 +103,941 @@ pub fn normal() -> ! {
                 if !is_collision(&game.field, &new_pos, &game.block) {
                     // ブロックの移動
                     game.pos = new_pos;
-                } else if landing(&mut game).is_err() {
-                    gameover(&game);
+                } else if let Err(reason) = land(&mut game, &history) {
+                    *over = Some(finish_normal(&game, reason, &history));
+                    continue;
                 }
 
                 // 裏データの描画
-                draw(&game);
+                draw_normal(&game, &show_hint);
             }
         });
     }
 
     // キー入力処理
-    let g = Getch::new();
     loop {
-        match g.getch() {
+        let key = terminal.getch();
+        {
+            // 自然落下処理と同じく、ゲームを先にロックする
+            let mut game = game.lock().unwrap();
+            let mut over = over.lock().unwrap();
+            if let Some(state) = over.as_mut() {
+                if let Some(entry) = state.entry.as_mut() {
+                    // ゲームオーバー後は名前の入力
+                    if enter_name(entry, key) {
+                        state.entry = None;
+                        print_retry_prompt(false);
+                    }
+                    continue;
+                }
+                match key {
+                    Ok(Key::Char('r')) => {
+                        // 最初の状態からやり直す
+                        *game = initial.clone();
+                        if let Some(history) = history.lock().unwrap().as_mut() {
+                            history.clear();
+                        }
+                        *over = None;
+                        println!("\x1b[2J");
+                        draw_normal(&game, &show_hint);
+                        continue;
+                    }
+                    Ok(Key::Char('q')) => gameover(&game, state.reason),
+                    Ok(Key::Char('u')) => {}
+                    _ => continue,
+                }
+            }
+        }
+        match key {
             Ok(Key::Left) => {
                 let mut game = game.lock().unwrap();
+                game.stats.keys += 1;
                 let new_pos = Position {
                     x: game.pos.x.checked_sub(1).unwrap_or(game.pos.x),
                     y: game.pos.y,
                 };
                 move_block(&mut game, new_pos);
-                draw(&game);
+                draw_normal(&game, &show_hint);
             }
             Ok(Key::Right) => {
                 let mut game = game.lock().unwrap();
+                game.stats.keys += 1;
                 let new_pos = Position {
                     x: game.pos.x + 1,
                     y: game.pos.y,
                 };
                 move_block(&mut game, new_pos);
-                draw(&game);
+                draw_normal(&game, &show_hint);
             }
             Ok(Key::Down) => {
                 let mut game = game.lock().unwrap();
+                game.stats.keys += 1;
                 let new_pos = Position {
                     x: game.pos.x,
                     y: game.pos.y + 1,
                 };
                 move_block(&mut game, new_pos);
-                draw(&game);
+                draw_normal(&game, &show_hint);
             }
             Ok(Key::Up) => {
                 let mut game = game.lock().unwrap();
+                game.stats.keys += 1;
                 hard_drop(&mut game);
-                if landing(&mut game).is_err() {
-                    gameover(&game);
+                if let Err(reason) = land(&mut game, &history) {
+                    *over.lock().unwrap() = Some(finish_normal(&game, reason, &history));
+                    continue;
                 }
-                draw(&game);
+                draw_normal(&game, &show_hint);
             }
             Ok(Key::Char('x')) => {
                 let mut game = game.lock().unwrap();
+                game.stats.keys += 1;
                 rotate_right(&mut game);
-                draw(&game);
+                draw_normal(&game, &show_hint);
             }
             Ok(Key::Char('z')) => {
                 let mut game = game.lock().unwrap();
+                game.stats.keys += 1;
                 rotate_left(&mut game);
-                draw(&game);
+                draw_normal(&game, &show_hint);
             }
             Ok(Key::Char(' ')) => {
                 let mut game = game.lock().unwrap();
+                game.stats.keys += 1;
                 hold(&mut game);
-                draw(&game);
+                draw_normal(&game, &show_hint);
+            }
+            Ok(Key::Char('h')) => {
+                // ヒント表示の切り替え
+                show_hint.fetch_xor(true, Ordering::Relaxed);
+                draw_normal(&game.lock().unwrap(), &show_hint);
+            }
+            Ok(Key::Char('u')) => {
+                // 1手巻き戻す(練習モードのみ)
+                let mut game = game.lock().unwrap();
+                if let Some(prev) = history.lock().unwrap().as_mut().and_then(History::undo) {
+                    let stats = &game.stats;
+                    let (keys, undos) = (stats.keys, stats.undos);
+                    *game = prev;
+                    game.stats.keys = keys;
+                    game.stats.undos = undos + 1;
+                    *over.lock().unwrap() = None;
+                    println!("\x1b[2J");
+                    draw_normal(&game, &show_hint);
+                }
+            }
+            Ok(Key::Char('d')) => {
+                // 現在の盤面をファイルに書き出す
+                let game = game.lock().unwrap();
+                dump_board(&game);
+            }
+            Ok(Key::Char('s')) => {
+                // ゲームを保存して終了する
+                let game = game.lock().unwrap();
+                let path = save::default_path();
+                match save::save(&game, &path) {
+                    Ok(()) => {
+                        let _stdout = term::restore();
+                        println!("saved to {}", path.display());
+                        quit();
+                    }
+                    Err(err) => show_message(&err),
+                }
+            }
+            Ok(Key::Char('q')) => quit(),
+            _ => {}
+        }
+    }
+}
+
+/// ブロックを着地させる
+/// 練習モードなら着地する前のゲームを履歴に残す
+fn land(game: &mut Game, history: &Mutex<Option<History>>) -> Result<(), GameOverReason> {
+    if let Some(history) = history.lock().unwrap().as_mut() {
+        history.push(game);
+    }
+    let result = landing(game);
+    announce(game);
+    result
+}
+
+/// 着地で起きたライン消去やレベルアップをメッセージに表示する
+fn announce(game: &mut Game) {
+    let mut messages = vec![];
+    for event in game.drain_events() {
+        match event {
+            Event::LinesCleared {
+                count,
+                spin,
+                b2b,
+                combo,
+                perfect_clear,
+            } => {
+                let name = ["", "SINGLE", "DOUBLE", "TRIPLE", "TETRIS"][count.min(4)];
+                let mut message = match (spin, b2b) {
+                    (true, true) => format!("B2B T-SPIN {name}"),
+                    (true, false) => format!("T-SPIN {name}"),
+                    (false, true) => format!("B2B {name}"),
+                    (false, false) => name.to_string(),
+                };
+                if combo > 1 {
+                    message += &format!(" {}REN", combo - 1);
+                }
+                if perfect_clear {
+                    message += " PC";
+                }
+                messages.push(message);
+            }
+            Event::LevelUp { level } => messages.push(format!("LEVEL {level}")),
+            _ => {}
+        }
+    }
+    // 描画領域の幅に収める
+    let message: String = messages
+        .join(" ")
+        .chars()
+        .take(term::MIN_WIDTH - 28)
+        .collect();
+    show_message(&message);
+}
+
+/// ゲームオーバーを表示する
+/// ハイスコアに載る場合は名前の入力を待ち、載らない場合や練習モードなら
+/// やり直すか(練習モードなら巻き戻すか)を待つ
+fn finish_normal(game: &Game, reason: GameOverReason, history: &Mutex<Option<History>>) -> Over {
+    show_gameover(game, reason);
+    let practice = history.lock().unwrap().is_some();
+    let entry = if practice { None } else { record_entry(game) };
+    if entry.is_none() {
+        print_retry_prompt(practice);
+    }
+    Over { reason, entry }
+}
+
+/// ハイスコアに載る場合は順位を表示し、名前を入力する記録を返す
+fn record_entry(game: &Game) -> Option<Entry> {
+    let entry = Entry::from_game(game, "normal", &game.rules.name());
+    let rank = match Scores::load() {
+        Ok(scores) => scores.rank(&entry),
+        Err(err) => {
+            println!("{}{err}", term::column(1));
+            None
+        }
+    };
+    match rank {
+        Some(1) => println!("{}NEW RECORD!", term::column(1)),
+        Some(rank) => println!("{}HIGH SCORE #{rank}", term::column(1)),
+        None => return None,
+    }
+    print_name_prompt(&entry.name);
+    Some(entry)
+}
+
+/// 名前の入力を1文字処理し、Enterで記録を保存する
+/// return: 保存したか
+fn enter_name(entry: &mut Entry, key: Result<Key, std::io::Error>) -> bool {
+    match key {
+        Ok(Key::Char('\r')) => {
+            if entry.name.is_empty() {
+                entry.name = "anonymous".to_string();
+            }
+            let result = Scores::load().and_then(|mut scores| {
+                scores.insert(entry.clone());
+                scores.save()
+            });
+            println!();
+            if let Err(err) = result {
+                println!("{}{err}", term::column(1));
+            }
+            return true;
+        }
+        Ok(Key::Backspace | Key::Delete) => {
+            entry.name.pop();
+        }
+        Ok(Key::Char(c)) if !c.is_control() && entry.name.chars().count() < MAX_NAME_LENGTH => {
+            entry.name.push(c);
+        }
+        _ => {}
+    }
+    print_name_prompt(&entry.name);
+    false
+}
+
+/// やり直すか終了するかを聞く
+fn print_retry_prompt(practice: bool) {
+    let undo = if practice { "u: undo  " } else { "" };
+    println!("{}{undo}r: retry  q: quit", term::column(1));
+}
+
+fn print_name_prompt(name: &str) {
+    print!("{}NAME: {name}\x1b[K", term::column(1));
+    io::stdout().flush().ok();
+}
+
+/// 現在の盤面をテキスト形式でカレントディレクトリに書き出す
+fn dump_board(game: &Game) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = format!("board-{secs}.txt");
+    let message = match fs::write(&path, Board::from_game(game).to_text()) {
+        Ok(()) => format!("saved {path}"),
+        Err(err) => format!("{err}"),
+    };
+    show_message(&message);
+}
+
+/// サイドバーの下にメッセージを表示する
+fn show_message(message: &str) {
+    if !term::too_small() {
+        println!("\x1b[0m{}{message}\x1b[K", term::goto(23, 28));
+    }
+}
+
+/// ヒント表示が有効ならAIのヒントを重ねて描画する
+fn draw_normal(game: &Game, show_hint: &AtomicBool) {
+    if show_hint.load(Ordering::Relaxed) {
+        draw_with_hint(game, Some(&hint(game)));
+    } else {
+        draw(game);
+    }
+}
+
+/// パズルモード
+/// 自然落下はなく、目標を達成すると次のパズルに進む
+pub fn puzzle(puzzles: Vec<Puzzle>, rules: Rules) -> ! {
+    // 代替画面に切り替える
+    let terminal = Terminal::enter();
+    term::reserve_rows(26);
+
+    let puzzles = Arc::new(puzzles);
+    // 遊んでいるパズルのゲームと進行状況と番号
+    let state = Arc::new(Mutex::new((
+        puzzles[0].game(rules),
+        Attempt::new(&puzzles[0]),
+        0,
+    )));
+    {
+        let (game, attempt, index) = &*state.lock().unwrap();
+        draw_puzzle(game, attempt, &puzzles, *index);
+    }
+
+    // 端末の大きさが変わったら描画し直す
+    {
+        let puzzles = Arc::clone(&puzzles);
+        let state = Arc::clone(&state);
+        term::watch_resize(move || {
+            let (game, attempt, index) = &*state.lock().unwrap();
+            draw_puzzle(game, attempt, &puzzles, *index);
+        });
+    }
+
+    loop {
+        let key = terminal.getch();
+        let mut state = state.lock().unwrap();
+        let (game, attempt, index) = &mut *state;
+        match attempt.status {
+            Status::Playing => {}
+            Status::Cleared if *index + 1 < puzzles.len() => {
+                // 次のパズルへ
+                *index += 1;
+                *game = puzzles[*index].game(rules);
+                *attempt = Attempt::new(&puzzles[*index]);
+                println!("\x1b[2J");
+                draw_puzzle(game, attempt, &puzzles, *index);
+                continue;
+            }
+            Status::Cleared => quit(),
+            Status::Failed => match key {
+                Ok(Key::Char('q')) => quit(),
+                Ok(Key::Char('r')) => {}
+                _ => continue,
+            },
+        }
+        if let Ok(Key::Left | Key::Right | Key::Down | Key::Up | Key::Char('x' | 'z' | ' ')) = key {
+            game.stats.keys += 1;
+        }
+        match key {
+            Ok(Key::Left) => {
+                let new_pos = Position {
+                    x: game.pos.x.checked_sub(1).unwrap_or(game.pos.x),
+                    y: game.pos.y,
+                };
+                move_block(game, new_pos);
+            }
+            Ok(Key::Right) => {
+                let new_pos = Position {
+                    x: game.pos.x + 1,
+                    y: game.pos.y,
+                };
+                move_block(game, new_pos);
+            }
+            Ok(Key::Down) => {
+                let new_pos = Position {
+                    x: game.pos.x,
+                    y: game.pos.y + 1,
+                };
+                move_block(game, new_pos);
+            }
+            Ok(Key::Up) => {
+                hard_drop(game);
+                attempt.landing(game);
+            }
+            Ok(Key::Char('x')) => rotate_right(game),
+            Ok(Key::Char('z')) => rotate_left(game),
+            Ok(Key::Char(' ')) => hold(game),
+            Ok(Key::Char('r')) => {
+                // やり直し
+                *game = puzzles[*index].game(rules);
+                *attempt = Attempt::new(&puzzles[*index]);
+            }
+            Ok(Key::Char('q')) => quit(),
+            _ => {}
+        }
+        draw_puzzle(game, attempt, &puzzles, *index);
+    }
+}
+
+/// 操作の効率を練習するモード
+/// 自然落下はなく、ブロックを置くたびに最短の操作と比べて無駄があれば指摘する
+pub fn finesse(game: Game) -> ! {
+    // 代替画面に切り替える
+    let terminal = Terminal::enter();
+    term::reserve_rows(25);
+
+    let initial = game.clone();
+    let trainer = FinesseTrainer::new(&game);
+    let state = Arc::new(Mutex::new((game, trainer)));
+    // ゲームオーバーになっていればその理由
+    let mut over = None;
+    {
+        let (game, trainer) = &*state.lock().unwrap();
+        draw(game);
+        trainer.draw();
+    }
+
+    // 端末の大きさが変わったら描画し直す
+    {
+        let state = Arc::clone(&state);
+        term::watch_resize(move || {
+            let (game, trainer) = &*state.lock().unwrap();
+            draw(game);
+            trainer.draw();
+        });
+    }
+
+    loop {
+        let key = terminal.getch();
+        let mut state = state.lock().unwrap();
+        let (game, trainer) = &mut *state;
+        if let Some(reason) = over {
+            match key {
+                Ok(Key::Char('r')) => {
+                    // 最初の状態からやり直す
+                    *game = initial.clone();
+                    *trainer = FinesseTrainer::new(game);
+                    over = None;
+                    println!("\x1b[2J");
+                    draw(game);
+                    trainer.draw();
+                }
+                Ok(Key::Char('q')) => gameover(game, reason),
+                _ => {}
+            }
+            continue;
+        }
+        let input = match key {
+            Ok(Key::Left) => Some(Input::Left),
+            Ok(Key::Right) => Some(Input::Right),
+            Ok(Key::Down) => Some(Input::Down),
+            Ok(Key::Char('x')) => Some(Input::RotateRight),
+            Ok(Key::Char('z')) => Some(Input::RotateLeft),
+            Ok(Key::Up) => {
+                game.stats.keys += 1;
+                hard_drop(game);
+                trainer.check(game);
+                if let Err(reason) = landing(game) {
+                    show_gameover(game, reason);
+                    print_retry_prompt(false);
+                    over = Some(reason);
+                    continue;
+                }
+                trainer.reset(game);
+                None
+            }
+            Ok(Key::Char(' ')) => {
+                game.stats.keys += 1;
+                hold(game);
+                trainer.reset(game);
+                None
+            }
+            Ok(Key::Char('q')) => quit(),
+            _ => None,
+        };
+        if let Some(input) = input {
+            game.stats.keys += 1;
+            input.apply(game);
+            trainer.inputs.push(input);
+        }
+        draw(game);
+        trainer.draw();
+    }
+}
+/// 操作の効率の記録
+struct FinesseTrainer {
+    spawn: Game,                       // ブロックが出てきたときのゲーム
+    inputs: Vec<Input>,                // 今のブロックに対する操作
+    faults: usize,                     // 無駄があった回数
+    pieces: usize,                     // 置いたブロック数
+    last: Option<(usize, Vec<Input>)>, // 直前のブロックで無駄があった場合の操作数と最短の操作
+}
+
+impl FinesseTrainer {
+    fn new(game: &Game) -> Self {
+        Self {
+            spawn: game.clone(),
+            inputs: vec![],
+            faults: 0,
+            pieces: 0,
+            last: None,
+        }
+    }
+
+    /// 新しいブロックが出てきた
+    fn reset(&mut self, game: &Game) {
+        self.spawn = game.clone();
+        self.inputs.clear();
+    }
+
+    /// ハードドロップする位置まで最短の操作で来たか調べる
+    fn check(&mut self, game: &Game) {
+        self.pieces += 1;
+        self.last = match minimal_inputs(&self.spawn, &placed_cells(game)) {
+            Some(minimal) if minimal.len() < self.inputs.len() => {
+                self.faults += 1;
+                Some((self.inputs.len(), minimal))
+            }
+            _ => None,
+        };
+    }
+
+    fn draw(&self) {
+        if term::too_small() {
+            return;
+        }
+        println!(
+            "\x1b[0m{}FINESSE FAULTS {}/{}\x1b[K",
+            term::goto(24, 28),
+            self.faults,
+            self.pieces
+        );
+        match &self.last {
+            Some((used, minimal)) => {
+                let minimal: Vec<&str> = minimal.iter().map(|input| input.to_symbol()).collect();
+                println!(
+                    "{}FAULT: {used} inputs, best {} ({})\x1b[K",
+                    term::goto(25, 28),
+                    minimal.len(),
+                    minimal.join(" ")
+                );
+            }
+            None => println!("{}\x1b[K", term::goto(25, 28)),
+        }
+    }
+}
+
+/// パズルの盤面と目標を描画する
+fn draw_puzzle(game: &Game, attempt: &Attempt, puzzles: &[Puzzle], index: usize) {
+    draw(game);
+    if term::too_small() {
+        return;
+    }
+    let puzzle = &puzzles[index];
+    println!(
+        "\x1b[0m{}PUZZLE {}/{} {}\x1b[K",
+        term::goto(24, 28),
+        index + 1,
+        puzzles.len(),
+        puzzle.title
+    );
+    println!("{}GOAL: {}\x1b[K", term::goto(25, 28), attempt.goal);
+    let message = match attempt.status {
+        Status::Playing => "",
+        Status::Cleared if index + 1 < puzzles.len() => "CLEAR! press any key",
+        Status::Cleared => "ALL CLEAR! press any key",
+        Status::Failed => "FAILED  r: retry  q: quit",
+    };
+    println!("{}{message}\x1b[K", term::goto(26, 28));
+
+    // 印の付いた行を示す
+    for y in 0..(FIELD_HEIGHT - 2) {
+        let mark = if attempt.marked.contains(&y) {
+            "<"
+        } else {
+            " "
+        };
+        println!("{}{mark}", term::goto(y + 2, (FIELD_WIDTH - 2) * 2 + 1));
+    }
+}
+
+/// キーが押され続けているとみなす時間
+/// 端末にはキーを離したことが届かないので、キーリピートが続いている間を押されているとみなす
+const HELD_WINDOW: Duration = Duration::from_millis(100);
+
+/// マスターモードのキー入力
+#[derive(Default)]
+struct MasterKeys {
+    left: Option<Instant>,  // 最後に押された時刻
+    right: Option<Instant>, // 最後に押された時刻
+    down: Option<Instant>,  // 最後に押された時刻
+    drop: bool,             // 次のフレームで押されたことにするか
+    rotate_right: bool,     // 次のフレームで押されたことにするか
+    rotate_left: bool,      // 次のフレームで押されたことにするか
+    hold: bool,             // 次のフレームで押されたことにするか
+}
+
+impl MasterKeys {
+    /// 1フレーム分の入力を取り出す
+    fn take(&mut self) -> master::Input {
+        let held = |time: Option<Instant>| time.is_some_and(|time| time.elapsed() < HELD_WINDOW);
+        master::Input {
+            left: held(self.left),
+            right: held(self.right),
+            down: held(self.down),
+            drop: std::mem::take(&mut self.drop),
+            rotate_right: std::mem::take(&mut self.rotate_right),
+            rotate_left: std::mem::take(&mut self.rotate_left),
+            hold: std::mem::take(&mut self.hold),
+        }
+    }
+}
+
+/// 20Gのマスターモード
+/// ゲームオーバーになるかレベル999に届いたら段位を表示し、最初の状態からやり直せる
+pub fn master(game: Game) -> ! {
+    // 代替画面に切り替える
+    let terminal = Terminal::enter();
+    term::reserve_rows(26);
+
+    // ゲームの初期化
+    let initial = game.clone();
+    let new_state = move || {
+        let mut game = initial.clone();
+        let mut master = Master::new();
+        master.start(&mut game);
+        (game, master, None)
+    };
+    // ゲームと進行状況と、ゲームオーバーになっていればその理由
+    let state: Arc<Mutex<(Game, Master, Option<GameOverReason>)>> =
+        Arc::new(Mutex::new(new_state()));
+    let keys = Arc::new(Mutex::new(MasterKeys::default()));
+
+    // 端末の大きさが変わったら描画し直す
+    {
+        let state = Arc::clone(&state);
+        term::watch_resize(move || {
+            let (game, master, _) = &*state.lock().unwrap();
+            draw_master(game, master);
+        });
+    }
+
+    // 1フレームごとに進める
+    {
+        let state = Arc::clone(&state);
+        let keys = Arc::clone(&keys);
+        thread::spawn(move || {
+            let frame = Duration::from_micros(1_000_000 / FPS);
+            let mut next = Instant::now();
+            loop {
+                next += frame;
+                sleep(next.saturating_duration_since(Instant::now()));
+
+                let mut state = state.lock().unwrap();
+                let (game, master, over) = &mut *state;
+                if over.is_some() || master.phase == Phase::Cleared {
+                    // やり直すまで止める
+                    continue;
+                }
+                let input = keys.lock().unwrap().take();
+                let result = master.tick(game, input);
+                game.events.clear();
+                draw_master(game, master);
+                if let Err(reason) = result {
+                    show_gameover(game, reason);
+                    println!("{}GRADE    {}", term::column(1), master.grade());
+                    print_retry_prompt(false);
+                    *over = Some(reason);
+                } else if master.phase == Phase::Cleared {
+                    println!("{}CLEAR! GRADE {}", term::column(1), master.grade());
+                    print_retry_prompt(false);
+                }
+            }
+        });
+    }
+
+    // キー入力処理
+    loop {
+        let key = terminal.getch();
+        {
+            let mut state = state.lock().unwrap();
+            let (game, master, over) = &*state;
+            if over.is_some() || master.phase == Phase::Cleared {
+                match key {
+                    Ok(Key::Char('r')) => {
+                        // 最初の状態からやり直す
+                        *state = new_state();
+                        println!("\x1b[2J");
+                    }
+                    Ok(Key::Char('q')) => {
+                        let _stdout = term::restore();
+                        println!("LEVEL    {}", master.level);
+                        println!("GRADE    {}", master.grade());
+                        print_summary(&game.stats);
+                        quit();
+                    }
+                    _ => {}
+                }
+                continue;
             }
+        }
+        let mut keys = keys.lock().unwrap();
+        match key {
+            Ok(Key::Left) => keys.left = Some(Instant::now()),
+            Ok(Key::Right) => keys.right = Some(Instant::now()),
+            Ok(Key::Down) => keys.down = Some(Instant::now()),
+            Ok(Key::Up) => keys.drop = true,
+            Ok(Key::Char('x')) => keys.rotate_right = true,
+            Ok(Key::Char('z')) => keys.rotate_left = true,
+            Ok(Key::Char(' ')) => keys.hold = true,
             Ok(Key::Char('q')) => quit(),
             _ => {}
         }
+        if let Ok(Key::Left | Key::Right | Key::Down | Key::Up | Key::Char('x' | 'z' | ' ')) = key {
+            drop(keys);
+            state.lock().unwrap().0.stats.keys += 1;
+        }
+    }
+}
+
+/// マスターモードの盤面とレベル、段位、時間を描画する
+fn draw_master(game: &Game, master: &Master) {
+    draw(game);
+    if term::too_small() {
+        return;
     }
+    // 次に止まるレベル(セクションの最後か999)
+    let stop = ((master.level / SECTION + 1) * SECTION).min(MAX_LEVEL);
+    println!(
+        "\x1b[0m{}LEVEL {}/{stop}\x1b[K",
+        term::goto(24, 28),
+        master.level
+    );
+    println!("{}GRADE {}\x1b[K", term::goto(25, 28), master.grade());
+    println!(
+        "{}TIME  {}\x1b[K",
+        term::goto(26, 28),
+        format_time(master::frames_to_duration(master.frames))
+    );
 }
 
 /// オートプレイ
-pub fn auto() -> ! {
+/// ゲームオーバーになったら理由を表示し、最初の状態からやり直せる
+pub fn auto(game: Game) -> ! {
     // ゲームの初期化
-    let game = Arc::new(Mutex::new(Game::new()));
+    let initial = game.clone();
+    let game = Arc::new(Mutex::new(game));
+    // ゲームオーバーになっていればその理由
+    let over: Arc<Mutex<Option<GameOverReason>>> = Arc::new(Mutex::new(None));
 
-    // 画面クリア
-    println!("\x1b[2J\x1b[H\x1b[?25l");
+    // 代替画面に切り替える
+    let terminal = Terminal::enter();
 
     // 初回描画(フィールドの描画)
     draw(&game.lock().unwrap());
 
+    // 端末の大きさが変わったら描画し直す
+    {
+        let game = Arc::clone(&game);
+        term::watch_resize(move || draw(&game.lock().unwrap()));
+    }
+
     // 自動操作処理
-    thread::spawn(move || {
-        loop {
-            // 100ミリ秒毎に何かする
-            thread::sleep(Duration::from_millis(100));
+    {
+        let game = Arc::clone(&game);
+        let over = Arc::clone(&over);
+        thread::spawn(move || {
+            loop {
+                // 100ミリ秒毎に何かする
+                thread::sleep(Duration::from_millis(100));
 
-            // 必要な変数の取得
-            let mut game = game.lock().unwrap();
+                // 必要な変数の取得
+                let mut game = game.lock().unwrap();
+                let mut over = over.lock().unwrap();
+                if over.is_some() {
+                    // やり直すまで止める
+                    continue;
+                }
 
-            // 自動操作
-            let elite = eval(&game);
-            *game = elite;
+                // 自動操作
+                let elite = eval(&game);
+                *game = elite;
+
+                // 終了判定
+                if let Err(reason) = landing(&mut game) {
+                    show_gameover(&game, reason);
+                    print_retry_prompt(false);
+                    *over = Some(reason);
+                    continue;
+                }
+                draw(&game);
+            }
+        });
+    }
 
-            // 終了判定
-            if landing(&mut game).is_err() {
-                // ブロックを生成できないならゲームオーバー
-                gameover(&game);
+    // キー入力処理
+    loop {
+        let key = terminal.getch();
+        let mut game = game.lock().unwrap();
+        let mut over = over.lock().unwrap();
+        match (key, *over) {
+            (Ok(Key::Char('r')), Some(_)) => {
+                // 最初の状態からやり直す
+                *game = initial.clone();
+                *over = None;
+                println!("\x1b[2J");
+                draw(&game);
             }
-            draw(&game);
+            (Ok(Key::Char('q')), Some(reason)) => gameover(&game, reason),
+            (Ok(Key::Char('q')), None) => quit(),
+            _ => {}
         }
-    });
+    }
+}
+
+/// 外部のTBPボットによるオートプレイ
+/// ゲームオーバーになったら理由を表示し、最初の状態からやり直せる
+pub fn external_bot(program: &str, args: &[String], mut game: Game) -> ! {
+    // ボットの起動
+    let mut bot = match ExternalBot::launch(program, args) {
+        Ok(bot) => bot,
+        Err(err) => {
+            eprintln!("failed to launch bot: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    // ゲームの初期化(TBPに合わせてフィールドの幅を10にする)
+    narrow_field(&mut game.field);
+    bot.send(&FrontendMessage::Start(start_of(&game))).ok();
+    let initial = game.clone();
+    let game = Arc::new(Mutex::new(game));
+    // ゲームオーバーになっていればその理由
+    let over: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+
+    // 代替画面に切り替える
+    let terminal = Terminal::enter();
+
+    // 初回描画(フィールドの描画)
+    draw(&game.lock().unwrap());
+
+    // 端末の大きさが変わったら描画し直す
+    {
+        let game = Arc::clone(&game);
+        term::watch_resize(move || draw(&game.lock().unwrap()));
+    }
+
+    // 自動操作処理
+    {
+        let game = Arc::clone(&game);
+        let over = Arc::clone(&over);
+        thread::spawn(move || {
+            // ボットがゲームを進めているか
+            let mut running = true;
+            loop {
+                // 100ミリ秒毎に何かする
+                thread::sleep(Duration::from_millis(100));
+
+                // 必要な変数の取得
+                let mut game = game.lock().unwrap();
+                let mut over = over.lock().unwrap();
+                if over.is_some() {
+                    // やり直すまで止める
+                    continue;
+                }
+                if !running {
+                    // やり直したゲームをボットに伝える
+                    bot.send(&FrontendMessage::Start(start_of(&game))).ok();
+                    running = true;
+                }
+
+                // ボットに次の手を聞いて反映する
+                let result = match bot.suggest() {
+                    Ok(Some(mv)) => apply_move(&mut game, &mv)
+                        .map(|revealed| (mv, revealed))
+                        .map_err(|()| "invalid move".to_string()),
+                    Ok(None) => Err("bot gave up".to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                let result = result.and_then(|(mv, revealed)| {
+                    bot.send(&FrontendMessage::Play { mv }).ok();
+                    // 終了判定
+                    landing(&mut game).map_err(|reason| reason.to_string())?;
+                    Ok(revealed)
+                });
+                let revealed = match result {
+                    Ok(revealed) => revealed,
+                    Err(reason) => {
+                        bot.send(&FrontendMessage::Stop).ok();
+                        running = false;
+                        show_gameover(&game, &reason);
+                        print_retry_prompt(false);
+                        *over = Some(reason);
+                        continue;
+                    }
+                };
+
+                // 新しく見えるようになったブロックをボットに伝える
+                for piece in game.next.iter().rev().take(revealed + 1).rev() {
+                    if let Some(piece) = BlockKind::from_shape(piece) {
+                        bot.send(&FrontendMessage::NewPiece { piece }).ok();
+                    }
+                }
+                draw(&game);
+            }
+        });
+    }
 
     // キー入力処理
-    let g = Getch::new();
     loop {
-        if let Ok(Key::Char('q')) = g.getch() {
-            quit()
+        let key = terminal.getch();
+        let mut game = game.lock().unwrap();
+        let mut over = over.lock().unwrap();
+        match (key, over.as_ref()) {
+            (Ok(Key::Char('r')), Some(_)) => {
+                // 最初の状態からやり直す
+                *game = initial.clone();
+                *over = None;
+                println!("\x1b[2J");
+                draw(&game);
+            }
+            (Ok(Key::Char('q')), Some(reason)) => gameover(&game, reason),
+            (Ok(Key::Char('q')), None) => quit(),
+            _ => {}
         }
     }
 }
+
+/// 元の画面に戻り、ゲームオーバーの理由と統計を表示してプログラムを終了する
+pub fn gameover(game: &Game, reason: impl fmt::Display) -> ! {
+    let _stdout = term::restore();
+    println!("Game Over! ({reason})");
+    println!("SCORE    {}", game.score);
+    print_summary(&game.stats);
+    quit();
+}
+
+/// 端末を元に戻し、プログラムを終了する
+pub fn quit() -> ! {
+    let _stdout = term::restore();
+    std::process::exit(0);
+}
@@ -0,0 +1,381 @@
+//! パズルのテキスト形式
+//!
+//! ```text
+//! title: TSD
+//! goal: tsd
+//! queue: TI
+//! ...........
+//! ##...######
+//! #...#######*
+//! ---
+//! goal: lines 4
+//! ...
+//! ```
+//!
+//! - 盤面は [`Board`] のテキスト形式に `title` と `goal` を加えたもので、`---` の行で区切って複数のパズルを並べられる
+//! - `goal` は `lines N`(N段消す), `tsd`(Tスピンダブル), `pc`(パーフェクトクリア), `rows`(印を付けた行を全て消す)
+//! - 行の末尾に `*` を付けるとその行に印が付く
+//! - ブロックは `current` と `queue` の順番で出てきて、使い切ると失敗になる
+
+use std::{fmt, fs, path::Path};
+
+use crate::{
+    board::Board,
+    field::{block_mask, place_bits, FIELD_HEIGHT, FULL_ROW},
+    game::{is_tspin, landing, spawn_block, Game},
+    rules::Rules,
+};
+
+/// フィールドの高さ(底を除く)
+const HEIGHT: usize = FIELD_HEIGHT - 2;
+
+/// パズルの目標
+#[derive(Clone, Copy)]
+pub enum Goal {
+    Lines(usize), // N段消す
+    TspinDouble,  // Tスピンダブルをする
+    PerfectClear, // パーフェクトクリアをする
+    MarkedRows,   // 印を付けた行を全て消す
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Goal::Lines(lines) => write!(f, "clear {lines} lines"),
+            Goal::TspinDouble => write!(f, "T-spin double"),
+            Goal::PerfectClear => write!(f, "perfect clear"),
+            Goal::MarkedRows => write!(f, "clear marked rows"),
+        }
+    }
+}
+
+/// パズル
+#[derive(Clone)]
+pub struct Puzzle {
+    pub title: String,
+    pub board: Board,
+    pub goal: Goal,
+    pub marked: Vec<usize>, // 印を付けた行のy座標
+}
+
+impl Puzzle {
+    /// ファイルからパズルを全て読み込む
+    pub fn load(path: &Path) -> Result<Vec<Self>, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse_pack(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// `---` で区切られたパズルを全て読み込む
+    pub fn parse_pack(text: &str) -> Result<Vec<Self>, String> {
+        let mut chunks = vec![String::new()];
+        for line in text.lines() {
+            if line.trim().starts_with("---") {
+                chunks.push(String::new());
+            } else {
+                let chunk = chunks.last_mut().unwrap();
+                chunk.push_str(line);
+                chunk.push('\n');
+            }
+        }
+        let puzzles = chunks
+            .iter()
+            .filter(|chunk| {
+                chunk
+                    .lines()
+                    .any(|line| !line.trim().is_empty() && !line.trim().starts_with(';'))
+            })
+            .enumerate()
+            .map(|(i, chunk)| Self::parse(chunk).map_err(|err| format!("puzzle {}: {err}", i + 1)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if puzzles.is_empty() {
+            return Err("no puzzles".into());
+        }
+        Ok(puzzles)
+    }
+
+    /// パズルを1つ読み込む
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut title = String::new();
+        let mut goal = None;
+        let mut board_text = String::new();
+        let mut marks = vec![];
+
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some((key, value)) = line.split_once(':') {
+                match key.trim() {
+                    "title" => {
+                        title = value.trim().to_string();
+                        continue;
+                    }
+                    "goal" => {
+                        goal = Some(parse_goal(value.trim())?);
+                        continue;
+                    }
+                    _ => {}
+                }
+            } else if !line.is_empty() && !line.starts_with(';') {
+                // フィールドの行
+                let row = line.strip_suffix('*');
+                marks.push(row.is_some());
+                board_text.push_str(row.unwrap_or(line).trim_end());
+                board_text.push('\n');
+                continue;
+            }
+            board_text.push_str(line);
+            board_text.push('\n');
+        }
+
+        let board = Board::parse(&board_text)?;
+        if board.current.is_none() && board.queue.is_empty() {
+            return Err("puzzle needs `current` or `queue`".into());
+        }
+        let goal = goal.ok_or("puzzle needs `goal`")?;
+
+        // 下詰めなので、印の位置からy座標を求める
+        let top = HEIGHT - marks.len();
+        let marked: Vec<usize> = marks
+            .iter()
+            .enumerate()
+            .filter(|(_, &marked)| marked)
+            .map(|(y, _)| y + top)
+            .collect();
+        if matches!(goal, Goal::MarkedRows) && marked.is_empty() {
+            return Err("goal `rows` needs marked rows".into());
+        }
+
+        Ok(Self {
+            title,
+            board,
+            goal,
+            marked,
+        })
+    }
+
+    /// パズルの初期状態のゲーム
+    /// ネクストは補充せず、決められた順番で出す
+    pub fn game(&self, rules: Rules) -> Game {
+        let mut game = Game::with_rules(rand::random(), rules);
+        game.fixed_queue = true;
+        game.next.clear();
+        game.next_buf.clear();
+        self.board.apply(&mut game);
+        if self.board.current.is_none() {
+            spawn_block(&mut game).ok();
+        }
+        game
+    }
+}
+
+/// `lines 4` のような目標を読み込む
+fn parse_goal(text: &str) -> Result<Goal, String> {
+    let mut words = text.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("lines"), Some(lines)) => lines
+            .parse()
+            .map(Goal::Lines)
+            .map_err(|_| format!("invalid number of lines `{lines}`")),
+        (Some("tsd"), None) => Ok(Goal::TspinDouble),
+        (Some("pc"), None) => Ok(Goal::PerfectClear),
+        (Some("rows"), None) => Ok(Goal::MarkedRows),
+        _ => Err(format!("unknown goal `{text}`")),
+    }
+}
+
+/// パズルの状態
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Playing,
+    Cleared,
+    Failed,
+}
+
+/// パズルの進行状況
+pub struct Attempt {
+    pub goal: Goal,
+    pub lines: usize,       // 消したライン数
+    pub marked: Vec<usize>, // まだ消していない印の付いた行
+    pub status: Status,
+}
+
+impl Attempt {
+    pub fn new(puzzle: &Puzzle) -> Self {
+        Self {
+            goal: puzzle.goal,
+            lines: 0,
+            marked: puzzle.marked.clone(),
+            status: Status::Playing,
+        }
+    }
+
+    /// ブロックを着地させ、目標を達成したか判定する
+    /// ブロックを使い切るか、ブロックを生成できなければ失敗
+    pub fn landing(&mut self, game: &mut Game) -> Status {
+        if self.status != Status::Playing {
+            return self.status;
+        }
+
+        // 消える行を先に調べておく
+        let tspin = is_tspin(game);
+        let mut bits = *game.field.bits();
+        place_bits(&mut bits, &game.pos, &block_mask(&game.block));
+        let cleared: Vec<usize> = (0..HEIGHT)
+            .filter(|&y| bits[y] & FULL_ROW == FULL_ROW)
+            .collect();
+
+        let result = landing(game);
+        self.lines += cleared.len();
+
+        // 印の付いた行を消えた行の分だけ下にずらす
+        self.marked = self
+            .marked
+            .iter()
+            .filter(|y| !cleared.contains(y))
+            .map(|&y| y + cleared.iter().filter(|&&c| c > y).count())
+            .collect();
+
+        let achieved = match self.goal {
+            Goal::Lines(lines) => self.lines >= lines,
+            Goal::TspinDouble => tspin && cleared.len() == 2,
+            Goal::PerfectClear => !cleared.is_empty() && game.field.is_empty(),
+            Goal::MarkedRows => self.marked.is_empty(),
+        };
+        self.status = if achieved {
+            Status::Cleared
+        } else if result.is_err() {
+            Status::Failed
+        } else {
+            Status::Playing
+        };
+        self.status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::BlockKind,
+        game::{hard_drop, move_block, rotate_shape_right, Position},
+        rules::HoldRule,
+    };
+
+    /// Iブロックを右端に落として一番下の行を消す盤面
+    const LINE: &str = "queue: I\n#######....\n";
+
+    /// パズルを読み込み、最初のブロックをx座標まで動かして落とす
+    fn drop_at(text: &str, x: usize) -> (Game, Attempt, Status) {
+        let puzzle = Puzzle::parse(text).unwrap();
+        let mut game = puzzle.game(Rules::default());
+        let mut attempt = Attempt::new(&puzzle);
+        let y = game.pos.y;
+        move_block(&mut game, Position { x, y });
+        hard_drop(&mut game);
+        let status = attempt.landing(&mut game);
+        (game, attempt, status)
+    }
+
+    #[test]
+    fn parse_goals() {
+        assert!(matches!(parse_goal("lines 4"), Ok(Goal::Lines(4))));
+        assert!(matches!(parse_goal("tsd"), Ok(Goal::TspinDouble)));
+        assert!(matches!(parse_goal("pc"), Ok(Goal::PerfectClear)));
+        assert!(matches!(parse_goal("rows"), Ok(Goal::MarkedRows)));
+        assert_eq!(
+            parse_goal("lines x").err().as_deref(),
+            Some("invalid number of lines `x`")
+        );
+        assert_eq!(
+            parse_goal("tsd 2").err().as_deref(),
+            Some("unknown goal `tsd 2`")
+        );
+        assert_eq!(
+            Puzzle::parse(LINE).err().as_deref(),
+            Some("puzzle needs `goal`")
+        );
+        assert_eq!(
+            Puzzle::parse("goal: rows\nqueue: I\n#######....\n")
+                .err()
+                .as_deref(),
+            Some("goal `rows` needs marked rows")
+        );
+    }
+
+    #[test]
+    fn parse_pack_and_marks() {
+        let puzzles = Puzzle::parse_pack(&format!(
+            "title: first\ngoal: lines 1\n{LINE}---\ngoal: rows\nqueue: I\n#######....*\n#########..\n"
+        ))
+        .unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!(puzzles[0].title, "first");
+        assert_eq!(puzzles[1].marked, [HEIGHT - 2]);
+    }
+
+    #[test]
+    fn game_uses_rules() {
+        let rules = Rules {
+            preview: 1,
+            hold: HoldRule::Disabled,
+            ..Rules::default()
+        };
+        let game = Puzzle::parse(&format!("goal: lines 1\n{LINE}"))
+            .unwrap()
+            .game(rules);
+        assert_eq!(game.rules, rules);
+        assert_eq!(game.block, BlockKind::I.shape());
+        assert!(game.next.is_empty());
+    }
+
+    #[test]
+    fn lines_goal() {
+        let (game, attempt, status) = drop_at(&format!("goal: lines 1\n{LINE}"), 9);
+        assert!(status == Status::Cleared);
+        assert_eq!((game.line, attempt.lines), (1, 1));
+
+        // 消せる場所に置かなければ、ブロックを使い切って失敗する
+        let (_, attempt, status) = drop_at(&format!("goal: lines 1\n{LINE}"), 2);
+        assert!(status == Status::Failed);
+        assert_eq!(attempt.lines, 0);
+    }
+
+    #[test]
+    fn tspin_double_goal() {
+        let text = "goal: tsd\nqueue: T\n.#.........\n#...#######\n##.########\n";
+        let place = |rotated: bool| {
+            let puzzle = Puzzle::parse(text).unwrap();
+            let mut game = puzzle.game(Rules::default());
+            let mut attempt = Attempt::new(&puzzle);
+            // 下向きのTブロックを穴に入れる
+            game.block = rotate_shape_right(&rotate_shape_right(&BlockKind::T.shape()));
+            game.pos = Position {
+                x: 2,
+                y: HEIGHT - 3,
+            };
+            game.rotated = rotated;
+            let status = attempt.landing(&mut game);
+            (attempt, status)
+        };
+
+        let (attempt, status) = place(true);
+        assert!(status == Status::Cleared);
+        assert_eq!(attempt.lines, 2);
+
+        // 回転で入れていなければTスピンにならない
+        let (attempt, status) = place(false);
+        assert!(status == Status::Failed);
+        assert_eq!(attempt.lines, 2);
+    }
+
+    #[test]
+    fn perfect_clear_goal() {
+        let (game, _, status) = drop_at(&format!("goal: pc\n{LINE}"), 9);
+        assert!(status == Status::Cleared);
+        assert!(game.field.is_empty());
+
+        // ラインを消してもブロックが残れば失敗する
+        let (game, _, status) = drop_at("goal: pc\nqueue: I\n#..........\n#######....\n", 9);
+        assert!(status == Status::Failed);
+        assert!(!game.field.is_empty());
+    }
+}
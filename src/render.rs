@@ -0,0 +1,256 @@
+//! 端末への描画
+
+use std::fmt;
+
+use rust_tetris_tutorial::{
+    ai::Hint,
+    block::{block_kind, BlockKind, BlockShape, BLOCK_SIZE},
+    field::{Colors, BIG_LEFT, BIG_WIDTH, FIELD_HEIGHT, FIELD_WIDTH},
+    game::{get_ghost_pos, Game},
+    pieces,
+    rules::{HoldRule, Modifier, INVISIBLE_DELAY},
+    stats::{format_time, Stats},
+};
+
+use crate::{spectate, term, theme};
+
+pub fn draw(game: &Game) {
+    draw_with_hint(game, None);
+}
+
+/// AIのヒントを重ねて描画する
+#[allow(clippy::needless_range_loop)]
+pub fn draw_with_hint(game: &Game, hint: Option<&Hint>) {
+    // 観戦者に状態を送る
+    spectate::publish(game);
+
+    let Game {
+        field,
+        pos,
+        block,
+        hold,
+        holded,
+        next,
+        score,
+        stats,
+        rules,
+        lock_times,
+        ..
+    } = game;
+
+    // ネクストの数に合わせてサイドバーの行を決める
+    // 4つ以上表示する場合は1つあたり3行に詰める
+    let preview = rules.preview;
+    let slot = if preview > 3 { 3 } else { 4 };
+    let hint_row = (8 + preview * slot + usize::from(slot == 3)).max(20);
+    term::reserve_rows(hint_row + 2);
+
+    // 端末が小さすぎる場合は描画しない
+    term::refresh();
+    if term::too_small() {
+        term::draw_too_small();
+        return;
+    }
+
+    // 裏データの生成
+    let mut field_buf = *field.colors();
+
+    // インビジブルなら固定してしばらく経ったブロックを隠す
+    if rules.has(Modifier::Invisible) {
+        for y in 0..(FIELD_HEIGHT - 2) {
+            for x in 2..(FIELD_WIDTH - 2) {
+                let hidden = lock_times[y][x].is_none_or(|time| time.elapsed() >= INVISIBLE_DELAY);
+                if field_buf[y][x] != block_kind::WALL && hidden {
+                    field_buf[y][x] = block_kind::NONE;
+                }
+            }
+        }
+    }
+
+    // 裏データにゴーストブロックを書き込む
+    if !rules.has(Modifier::NoGhost) {
+        let ghost_pos = get_ghost_pos(field, pos, block);
+        for y in 0..BLOCK_SIZE {
+            for x in 0..BLOCK_SIZE {
+                if block[y][x] != block_kind::NONE {
+                    field_buf[y + ghost_pos.y][x + ghost_pos.x] = block_kind::GHOST;
+                }
+            }
+        }
+    }
+
+    // 裏データにヒントを書き込む
+    if let Some(hint) = hint {
+        for y in 0..BLOCK_SIZE {
+            for x in 0..BLOCK_SIZE {
+                if hint.block[y][x] != block_kind::NONE {
+                    field_buf[y + hint.pos.y][x + hint.pos.x] = block_kind::HINT;
+                }
+            }
+        }
+    }
+
+    // 裏データにブロックを書き込む
+    for y in 0..BLOCK_SIZE {
+        for x in 0..BLOCK_SIZE {
+            if block[y][x] != block_kind::NONE {
+                field_buf[y + pos.y][x + pos.x] = block[y][x];
+            }
+        }
+    }
+
+    // ビッグなら1マスを2x2に広げる
+    if rules.has(Modifier::Big) {
+        field_buf = enlarge(&field_buf);
+    }
+
+    // ホールドを描画(ホールドできない間は灰色にする)
+    let hold_locked = !rules.can_hold(*holded);
+    match rules.hold {
+        HoldRule::Disabled => println!("{}{}HOLD -\x1b[0m", term::goto(2, 28), theme::dim()),
+        _ if hold_locked => println!("{}{}HOLD\x1b[0m", term::goto(2, 28), theme::dim()),
+        _ => println!("{}HOLD", term::goto(2, 28)),
+    }
+    if let Some(hold) = hold {
+        let top = preview_top(hold, 4);
+        for y in 0..4 {
+            print!("{}", term::goto(y + 3, 28));
+            for x in 0..BLOCK_SIZE {
+                let color = match hold[y + top][x] {
+                    color if hold_locked && color != block_kind::NONE => block_kind::GHOST,
+                    color => color,
+                };
+                print!("{}", theme::cell(color));
+            }
+            println!();
+        }
+    }
+
+    // ネクストブロックたちを描画
+    if preview > 0 {
+        println!("{}NEXT", term::goto(8, 28)); // カーソルをネクスト位置に移動
+    }
+    for i in 0..preview {
+        let top = next.get(i).map_or(0, |next| preview_top(next, slot));
+        for y in 0..slot {
+            print!("{}", term::goto(i * slot + y + 9, 28)); // カーソルを移動
+            for x in 0..BLOCK_SIZE {
+                // ネクストが足りない場合は空白を描画
+                let color = next
+                    .get(i)
+                    .map_or(block_kind::NONE, |next| next[y + top][x]);
+                print!("{}", theme::cell(color));
+            }
+            println!();
+        }
+    }
+
+    // ヒントの説明を描画
+    let hint_pos = term::goto(hint_row, 28);
+    match hint {
+        Some(Hint { hold: true, .. }) => println!("\x1b[0m{hint_pos}HINT: HOLD\x1b[K"),
+        Some(_) => println!("\x1b[0m{hint_pos}HINT\x1b[K"),
+        None => println!("\x1b[0m{hint_pos}\x1b[K"),
+    }
+
+    // スコアを描画
+    println!("{}{score}", term::goto(hint_row + 2, 28));
+
+    // 統計を描画
+    draw_stats(stats);
+
+    // 裏データの描画
+    for y in 0..(FIELD_HEIGHT - 1) {
+        print!("{}", term::goto(y + 2, 1)); // カーソルを行の先頭へ移動
+        for x in 1..(FIELD_WIDTH - 1) {
+            print!("{}", theme::cell(field_buf[y][x]))
+        }
+        println!();
+    }
+
+    // 色情報をリセット
+    println!("\x1b[0m");
+}
+
+/// ホールドとネクストの描画を始める行
+/// 下の方までセルがあるブロックだけ、`rows` 行に収まるように上へずらす
+fn preview_top(block: &BlockShape, rows: usize) -> usize {
+    let bottom = (0..BLOCK_SIZE)
+        .rev()
+        .find(|&y| block[y].iter().any(|&cell| cell != block_kind::NONE))
+        .unwrap_or(0);
+    (bottom + 1).saturating_sub(rows)
+}
+
+/// ビッグのフィールドを2倍に広げる
+/// 余った右端の列は壁にする
+fn enlarge(field: &Colors) -> Colors {
+    let mut enlarged = *field;
+    for y in 0..(FIELD_HEIGHT - 2) {
+        for x in 2..(FIELD_WIDTH - 2) {
+            enlarged[y][x] = if x - 2 < BIG_WIDTH * 2 {
+                field[y / 2][BIG_LEFT + (x - 2) / 2]
+            } else {
+                block_kind::WALL
+            };
+        }
+    }
+    enlarged
+}
+
+/// サイドバーに統計を描画する
+pub fn draw_stats(stats: &Stats) {
+    let lines = [
+        format!("TIME   {}", format_time(stats.elapsed())),
+        format!("PIECES {}", stats.pieces),
+        format!("PPS    {:.2}", stats.pps()),
+        format!("KPP    {:.2}", stats.kpp()),
+        format!("LPM    {:.1}", stats.lpm()),
+        format!("APM    {:.1}", stats.apm()),
+        format!("COMBO  {}", stats.max_combo),
+    ];
+    for (i, line) in lines.iter().enumerate() {
+        println!("\x1b[0m{}{line}\x1b[K", term::goto(i + 2, 40));
+    }
+}
+
+/// ゲームオーバー時に全ての統計を表示する
+pub fn print_summary(stats: &Stats) {
+    let col = term::column(1);
+    println!("{col}TIME     {}", format_time(stats.elapsed()));
+    println!(
+        "{col}PIECES   {} ({:.2} PPS, {:.2} KPP)",
+        stats.pieces,
+        stats.pps(),
+        stats.kpp()
+    );
+    println!("{col}LINES    {} ({:.1} LPM)", stats.lines, stats.lpm());
+    println!("{col}ATTACK   {} ({:.1} APM)", stats.attack, stats.apm());
+    if pieces::current().is_standard() {
+        let kinds: Vec<String> = BlockKind::ALL
+            .iter()
+            .map(|&kind| format!("{}:{}", kind.to_char(), stats.kinds[kind as usize]))
+            .collect();
+        println!("{col}PIECE    {}", kinds.join(" "));
+    }
+    println!(
+        "{col}CLEAR    single:{} double:{} triple:{} tetris:{}",
+        stats.clears[1], stats.clears[2], stats.clears[3], stats.clears[4]
+    );
+    println!(
+        "{col}T-SPIN   zero:{} single:{} double:{} triple:{}",
+        stats.tspins[0], stats.tspins[1], stats.tspins[2], stats.tspins[3]
+    );
+    println!("{col}PC       {}", stats.perfect_clears);
+    println!("{col}COMBO    {}", stats.max_combo);
+    if stats.undos > 0 {
+        println!("{col}UNDO     {}", stats.undos);
+    }
+}
+
+/// 盤面を描画し、ゲームオーバーの理由と統計を表示する
+pub fn show_gameover(game: &Game, reason: impl fmt::Display) {
+    draw(game);
+    println!("{}Game Over! ({reason})", term::column(1));
+    print_summary(&game.stats);
+}
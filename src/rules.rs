@@ -0,0 +1,189 @@
+//! ルールの設定
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{pieces, scores::DEFAULT_RULESET};
+
+/// ネクストの表示数の最大値
+pub const MAX_PREVIEW: usize = 7;
+/// 見えなくなるまでの時間(インビジブル)
+pub const INVISIBLE_DELAY: Duration = Duration::from_secs(3);
+/// フィールドを反転する間隔のブロック数(ミラー)
+pub const MIRROR_INTERVAL: usize = 10;
+
+/// ホールドのルール
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "tui", derive(clap::ValueEnum))]
+pub enum HoldRule {
+    /// ホールドできない
+    Disabled,
+    /// 1つのブロックにつき1回だけ
+    #[default]
+    Once,
+    /// 何回でも
+    Unlimited,
+}
+
+/// 回転法則
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "tui", derive(clap::ValueEnum))]
+pub enum RotationSystem {
+    /// 上下左右にずらして入れる(スーパーローテーション)
+    #[default]
+    Super,
+    /// アリカ式(左右に1マスだけずらして入れる)
+    Arika,
+}
+
+/// どのモードにも重ねて使えるゲームの変化
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "tui", derive(clap::ValueEnum))]
+pub enum Modifier {
+    /// 固定したブロックがしばらくすると見えなくなる
+    Invisible,
+    /// ブロックの1マスを2x2にして、半分の解像度のフィールドで遊ぶ
+    Big,
+    /// 一定のブロック数ごとにフィールドを左右反転する
+    Mirror,
+    /// ゴーストを表示しない
+    NoGhost,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 4] = [
+        Modifier::Invisible,
+        Modifier::Big,
+        Modifier::Mirror,
+        Modifier::NoGhost,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Modifier::Invisible => "invisible",
+            Modifier::Big => "big",
+            Modifier::Mirror => "mirror",
+            Modifier::NoGhost => "no-ghost",
+        }
+    }
+}
+
+/// 有効なゲームの変化の組み合わせ
+/// 保存するときは名前のリストにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Modifier>", into = "Vec<Modifier>")]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub fn contains(self, modifier: Modifier) -> bool {
+        self.0 & (1 << modifier as u8) != 0
+    }
+
+    pub fn insert(&mut self, modifier: Modifier) {
+        self.0 |= 1 << modifier as u8;
+    }
+
+    /// 有効なものを順に返す
+    pub fn iter(self) -> impl Iterator<Item = Modifier> {
+        Modifier::ALL
+            .into_iter()
+            .filter(move |&modifier| self.contains(modifier))
+    }
+}
+
+impl FromIterator<Modifier> for Modifiers {
+    fn from_iter<I: IntoIterator<Item = Modifier>>(iter: I) -> Self {
+        let mut modifiers = Self::default();
+        for modifier in iter {
+            modifiers.insert(modifier);
+        }
+        modifiers
+    }
+}
+
+impl From<Vec<Modifier>> for Modifiers {
+    fn from(list: Vec<Modifier>) -> Self {
+        list.into_iter().collect()
+    }
+}
+
+impl From<Modifiers> for Vec<Modifier> {
+    fn from(modifiers: Modifiers) -> Self {
+        modifiers.iter().collect()
+    }
+}
+
+/// ゲームのルール
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rules {
+    pub preview: usize, // ネクストの表示数(0~7)
+    pub hold: HoldRule, // ホールドのルール
+    #[serde(default)]
+    pub rotation: RotationSystem, // 回転法則
+    #[serde(default)]
+    pub modifiers: Modifiers, // ゲームの変化
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            preview: 3,
+            hold: HoldRule::Once,
+            rotation: RotationSystem::Super,
+            modifiers: Modifiers::default(),
+        }
+    }
+}
+
+impl Rules {
+    /// ホールドできるか
+    pub fn can_hold(&self, holded: bool) -> bool {
+        match self.hold {
+            HoldRule::Disabled => false,
+            HoldRule::Once => !holded,
+            HoldRule::Unlimited => true,
+        }
+    }
+
+    /// ゲームの変化が有効か
+    pub fn has(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(modifier)
+    }
+
+    /// ハイスコアの表を分けるためのルール名
+    /// ゲームの変化は `+big`、7種類以外のピースセットは `+pentomino` のように後ろに付ける
+    pub fn name(&self) -> String {
+        let base = Self {
+            modifiers: Modifiers::default(),
+            ..*self
+        };
+        let mut name = if base == Self::default() {
+            DEFAULT_RULESET.to_string()
+        } else {
+            let hold = match self.hold {
+                HoldRule::Disabled => "disabled",
+                HoldRule::Once => "once",
+                HoldRule::Unlimited => "unlimited",
+            };
+            let rotation = match self.rotation {
+                RotationSystem::Super => "",
+                RotationSystem::Arika => "-arika",
+            };
+            format!("next{}-hold-{hold}{rotation}", self.preview)
+        };
+        for modifier in self.modifiers.iter() {
+            name += "+";
+            name += modifier.name();
+        }
+        // 7種類以外のブロックを使う場合はピースセットの名前を付ける
+        let pieces = pieces::current();
+        if !pieces.is_standard() {
+            name += "+";
+            name += &pieces.name;
+        }
+        name
+    }
+}
@@ -0,0 +1,104 @@
+//! 中断したゲームの保存と再開
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::Game,
+    pieces,
+    scores::{data_dir, write_atomic},
+};
+
+/// 保存先のデフォルトのパス
+pub fn default_path() -> PathBuf {
+    data_dir().join("save.json")
+}
+
+/// 保存ファイルの中身
+/// ピースセットの名前を記録して、別のセットで再開しないようにする
+#[derive(Serialize, Deserialize)]
+struct SaveFile<G> {
+    pieces: String, // 使っていたピースセットの名前
+    game: G,
+}
+
+/// ゲームを保存する
+/// 乱数の状態も保存するので、再開後も同じ順番でブロックが出てくる
+pub fn save(game: &Game, path: &Path) -> Result<(), String> {
+    let file = SaveFile {
+        pieces: pieces::current().name.clone(),
+        game,
+    };
+    let json = serde_json::to_string(&file).map_err(|err| err.to_string())?;
+    write_atomic(path, json.as_bytes()).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// 保存したゲームを読み込み、保存ファイルを消す
+/// 使っているピースセットが保存したときと違う場合はエラーを返す(保存ファイルは残す)
+pub fn resume(path: &Path) -> Result<Game, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    // ピースセットの名前がない古い形式はゲームだけを保存していて、7種類のブロックで遊んでいたもの
+    let file = serde_json::from_str(&text)
+        .or_else(|err| {
+            serde_json::from_str(&text)
+                .map(|game| SaveFile {
+                    pieces: "tetromino".into(),
+                    game,
+                })
+                .map_err(|_| err)
+        })
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    if file.pieces != pieces::current().name {
+        return Err(format!(
+            "{}: the game was saved with the `{}` piece set (choose it with `--pieces`)",
+            path.display(),
+            file.pieces
+        ));
+    }
+    fs::remove_file(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(file.game)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn save_and_resume() {
+        let path = env::temp_dir().join(format!("save-{}.json", std::process::id()));
+        let game = Game::with_seed(0);
+        save(&game, &path).unwrap();
+        let resumed = resume(&path).unwrap();
+        assert_eq!(resumed.next, game.next);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn resume_old_save() {
+        let path = env::temp_dir().join(format!("save-old-{}.json", std::process::id()));
+        let game = Game::with_seed(0);
+        fs::write(&path, serde_json::to_string(&game).unwrap()).unwrap();
+        let resumed = resume(&path).unwrap();
+        assert_eq!(resumed.next, game.next);
+    }
+
+    #[test]
+    fn resume_with_other_piece_set() {
+        let path = env::temp_dir().join(format!("save-pentomino-{}.json", std::process::id()));
+        let file = SaveFile {
+            pieces: "pentomino".into(),
+            game: Game::with_seed(0),
+        };
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        let err = resume(&path).err().unwrap();
+        assert!(err.contains("the game was saved with the `pentomino` piece set"));
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
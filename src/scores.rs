@@ -0,0 +1,219 @@
+//! ハイスコアの記録
+//!
+//! モードとルールごとに上位 [`MAX_ENTRIES`] 件をJSONファイルに保存する
+
+use std::{
+    cmp::Reverse,
+    env,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::Game;
+
+/// モードとルールごとに残す件数
+pub const MAX_ENTRIES: usize = 10;
+/// 標準のルール
+pub const DEFAULT_RULESET: &str = "standard";
+
+/// ハイスコアの1件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub mode: String,
+    pub ruleset: String,
+    pub score: usize,
+    pub lines: usize,
+    pub time: f64, // プレイ時間(秒)
+    pub seed: u64,
+    pub date: u64, // 記録した日時(UNIX時間)
+}
+
+impl Entry {
+    /// 終了したゲームから記録を作る(名前は後で入れる)
+    pub fn from_game(game: &Game, mode: &str, ruleset: &str) -> Self {
+        Self {
+            name: String::new(),
+            mode: mode.to_string(),
+            ruleset: ruleset.to_string(),
+            score: game.score,
+            lines: game.line,
+            time: game.stats.elapsed().as_secs_f64(),
+            seed: game.seed,
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+        }
+    }
+
+    /// 同じ表に載る記録か
+    fn same_table(&self, other: &Entry) -> bool {
+        self.mode == other.mode && self.ruleset == other.ruleset
+    }
+
+    /// 順位の比較(スコアが高く、同じならライン数が多いほうが上)
+    fn beats(&self, other: &Entry) -> bool {
+        (self.score, self.lines) > (other.score, other.lines)
+    }
+}
+
+/// ハイスコアの表
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Scores {
+    pub entries: Vec<Entry>,
+}
+
+impl Scores {
+    /// 保存されている表を読み込む
+    /// ファイルがなければ空の表を返す
+    pub fn load() -> Result<Self, String> {
+        let path = scores_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|err| format!("{}: {err}", path.display()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{}: {err}", path.display())),
+        }
+    }
+
+    /// 表を保存する
+    pub fn save(&self) -> Result<(), String> {
+        let path = scores_path();
+        let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        write_atomic(&path, json.as_bytes()).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// 記録が表に載る場合の順位(1から)
+    pub fn rank(&self, entry: &Entry) -> Option<usize> {
+        let table = self.table(&entry.mode, &entry.ruleset);
+        let rank = table.iter().filter(|other| !entry.beats(other)).count() + 1;
+        (rank <= MAX_ENTRIES).then_some(rank)
+    }
+
+    /// 記録を追加し、表に載らなくなった記録を消す
+    pub fn insert(&mut self, entry: Entry) {
+        let rank = self.rank(&entry);
+        if rank.is_none() {
+            return;
+        }
+        self.entries.push(entry.clone());
+        self.entries.sort_by(|a, b| {
+            (b.score, b.lines)
+                .cmp(&(a.score, a.lines))
+                .then(a.date.cmp(&b.date))
+        });
+        let mut count = 0;
+        self.entries.retain(|other| {
+            if !other.same_table(&entry) {
+                return true;
+            }
+            count += 1;
+            count <= MAX_ENTRIES
+        });
+    }
+
+    /// モードとルールが一致する記録(順位順)
+    pub fn table(&self, mode: &str, ruleset: &str) -> Vec<&Entry> {
+        let mut table: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.mode == mode && entry.ruleset == ruleset)
+            .collect();
+        table.sort_by_key(|entry| Reverse((entry.score, entry.lines)));
+        table
+    }
+
+    /// 表形式の文字列にする
+    /// モードやルールを指定しない場合は全ての表を並べる
+    pub fn format_table(&self, mode: Option<&str>, ruleset: Option<&str>, limit: usize) -> String {
+        let mut tables: Vec<(&str, &str)> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.mode.as_str(), entry.ruleset.as_str()))
+            .filter(|(m, r)| {
+                mode.is_none_or(|mode| mode == *m) && ruleset.is_none_or(|ruleset| ruleset == *r)
+            })
+            .collect();
+        tables.sort();
+        tables.dedup();
+        if tables.is_empty() {
+            return "no scores\n".to_string();
+        }
+        let mut text = String::new();
+        for (mode, ruleset) in tables {
+            writeln!(text, "{mode} ({ruleset})").unwrap();
+            writeln!(
+                text,
+                "{:>4} {:<16} {:>8} {:>6} {:>9} {:>20} {:>10}",
+                "rank", "name", "score", "lines", "time", "seed", "date"
+            )
+            .unwrap();
+            for (i, entry) in self.table(mode, ruleset).iter().take(limit).enumerate() {
+                writeln!(
+                    text,
+                    "{:>4} {:<16} {:>8} {:>6} {:>9} {:>20} {:>10}",
+                    i + 1,
+                    entry.name,
+                    entry.score,
+                    entry.lines,
+                    format!("{:.1}s", entry.time),
+                    entry.seed,
+                    format_date(entry.date)
+                )
+                .unwrap();
+            }
+            writeln!(text).unwrap();
+        }
+        text
+    }
+}
+
+/// データを保存するディレクトリ
+/// `TETRIS_DATA_DIR`、`XDG_DATA_HOME`、`~/.local/share` の順に探す
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("TETRIS_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+    let base = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("rust-tetris-tutorial")
+}
+
+fn scores_path() -> PathBuf {
+    data_dir().join("scores.json")
+}
+
+/// 一時ファイルに書き込んでから置き換えることで、途中で落ちても壊れないようにする
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// UNIX時間を `YYYY-MM-DD` にする
+fn format_date(secs: u64) -> String {
+    // 0000-03-01 からの日数に直して計算する
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,321 @@
+//! 観戦用の状態の配信
+//!
+//! プレイ中のゲームの状態を1行1つのJSONとしてTCPかUnixソケットで配信し、
+//! `watch` で同じ描画処理を使って表示する
+//!
+//! ```text
+//! {"field":["1000000000000001",...],"pos":{"x":5,"y":0},"block":["0000","9990",...],"hold":"I","holded":false,"next":"SZO","score":0,...}
+//! ```
+//!
+//! - アドレスは `host:port`(TCP)か `unix:path`(Unixソケット)
+//! - `field` と `block` は各行のセルの色を36進数1文字で表したもの
+//! - `hold` と `next` はピースセットのブロックの名前(観戦者も同じピースセットを使う)
+//! - 観戦者はいつでも接続、切断でき、送るのが間に合わない観戦者の分は状態を間引く
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::{
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+};
+
+use getch_rs::Key;
+use rust_tetris_tutorial::{
+    block::{BlockKind, BlockShape, BLOCK_SIZE},
+    field::{Colors, Field, FIELD_HEIGHT, FIELD_WIDTH},
+    game::{Game, Position},
+    pieces,
+    rules::Rules,
+    stats::Stats,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    play::quit,
+    render::draw,
+    term::{self, Terminal},
+};
+
+/// 観戦者ごとに溜めておく状態の数(超えた分は捨てる)
+const FRAME_BUFFER: usize = 4;
+
+/// 配信するアドレス
+#[derive(Debug, Clone)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl Address {
+    /// `unix:` で始まればUnixソケット、それ以外はTCPのアドレスとして読む
+    pub fn parse(text: &str) -> Self {
+        match text.strip_prefix("unix:") {
+            Some(path) => Address::Unix(PathBuf::from(path)),
+            None => Address::Tcp(text.to_string()),
+        }
+    }
+
+    /// 観戦者として接続する
+    fn connect(&self) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Address::Tcp(addr) => Box::new(TcpStream::connect(addr)?),
+            Address::Unix(path) => Box::new(UnixStream::connect(path)?),
+        })
+    }
+}
+
+/// 配信する状態
+#[derive(Serialize, Deserialize)]
+pub struct Frame {
+    pub field: Vec<String>, // フィールドの各行(番兵を含む)
+    pub pos: Position,      // 現在のブロックの位置
+    pub block: Vec<String>, // 現在のブロックの各行
+    pub hold: Option<char>, // ホールドしたブロック
+    pub holded: bool,       // ホールド済みか
+    pub next: String,       // 次のブロック
+    pub score: usize,
+    pub line: usize,
+    pub rules: Rules,
+    pub stats: Stats,
+}
+
+impl Frame {
+    pub fn from_game(game: &Game) -> Self {
+        let kinds = |shapes: &mut dyn Iterator<Item = &BlockShape>| -> String {
+            shapes
+                .filter_map(|shape| pieces::current().of(shape))
+                .map(|piece| piece.name)
+                .collect()
+        };
+        Self {
+            field: game
+                .field
+                .colors()
+                .iter()
+                .map(|row| encode_row(row))
+                .collect(),
+            pos: game.pos,
+            block: game.block.iter().map(|row| encode_row(row)).collect(),
+            hold: game
+                .hold
+                .as_ref()
+                .and_then(BlockKind::from_shape)
+                .map(BlockKind::to_char),
+            holded: game.holded,
+            next: kinds(&mut game.next.iter().take(game.rules.preview)),
+            score: game.score,
+            line: game.line,
+            rules: game.rules,
+            stats: game.stats.clone(),
+        }
+    }
+
+    /// 描画するためのゲームを作る
+    pub fn to_game(&self) -> Result<Game, String> {
+        let mut game = Game::with_rules(0, self.rules);
+        let mut colors: Colors = [[0; FIELD_WIDTH]; FIELD_HEIGHT];
+        if self.field.len() != FIELD_HEIGHT {
+            return Err("invalid field".into());
+        }
+        for (row, text) in colors.iter_mut().zip(&self.field) {
+            decode_row(text, row)?;
+        }
+        game.field = Field::from(colors);
+        // 4x4で配信していた形も読めるようにする
+        let mut block = BlockShape::default();
+        if self.block.len() > BLOCK_SIZE {
+            return Err("invalid block".into());
+        }
+        for (row, text) in block.iter_mut().zip(&self.block) {
+            let len = text.chars().count().min(BLOCK_SIZE);
+            decode_row(text, &mut row[..len])?;
+        }
+        game.block = block;
+        game.pos = self.pos;
+        let shape = |c: char| {
+            pieces::current()
+                .by_name(c)
+                .map(|piece| piece.shape)
+                .or_else(|| BlockKind::from_char(c).map(BlockKind::shape))
+                .ok_or_else(|| format!("invalid piece `{c}`"))
+        };
+        game.hold = self.hold.map(shape).transpose()?;
+        game.holded = self.holded;
+        game.next = self.next.chars().map(shape).collect::<Result<_, _>>()?;
+        game.score = self.score;
+        game.line = self.line;
+        game.stats = self.stats.clone();
+        Ok(game)
+    }
+}
+
+/// セルの色を36進数1文字ずつに変換する
+fn encode_row(row: &[usize]) -> String {
+    row.iter()
+        .map(|&color| char::from_digit(color as u32, 36).unwrap_or('0'))
+        .collect()
+}
+
+fn decode_row(text: &str, row: &mut [usize]) -> Result<(), String> {
+    if text.chars().count() != row.len() {
+        return Err(format!("invalid row `{text}`"));
+    }
+    for (cell, c) in row.iter_mut().zip(text.chars()) {
+        *cell = c
+            .to_digit(36)
+            .ok_or_else(|| format!("invalid row `{text}`"))? as usize;
+    }
+    Ok(())
+}
+
+/// 状態の配信
+struct Publisher {
+    viewers: Mutex<Vec<SyncSender<Arc<str>>>>, // 観戦者ごとの送信スレッドへの送り口
+    last: Mutex<Option<Arc<str>>>,             // 最後に配信した状態(接続したときに送る)
+}
+
+impl Publisher {
+    /// 観戦者を追加し、送信スレッドを起動する
+    fn attach(&self, mut stream: Box<dyn Write + Send>) {
+        let (sender, receiver) = mpsc::sync_channel::<Arc<str>>(FRAME_BUFFER);
+        if let Some(last) = self.last.lock().unwrap().clone() {
+            sender.try_send(last).ok();
+        }
+        self.viewers.lock().unwrap().push(sender);
+        thread::spawn(move || {
+            for line in receiver {
+                if writeln!(stream, "{line}")
+                    .and_then(|()| stream.flush())
+                    .is_err()
+                {
+                    // 切断された
+                    break;
+                }
+            }
+        });
+    }
+
+    /// 全ての観戦者に送る
+    /// 送信は観戦者ごとのスレッドで行うので、ゲームの処理は待たない
+    fn broadcast(&self, line: Arc<str>) {
+        *self.last.lock().unwrap() = Some(Arc::clone(&line));
+        self.viewers
+            .lock()
+            .unwrap()
+            .retain(|sender| match sender.try_send(Arc::clone(&line)) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+static PUBLISHER: OnceLock<Publisher> = OnceLock::new();
+
+/// アドレスで待ち受けを始め、以降に描画するゲームの状態を配信する
+pub fn publish_on(address: &Address) -> Result<(), String> {
+    let publisher = PUBLISHER.get_or_init(|| Publisher {
+        viewers: Mutex::new(vec![]),
+        last: Mutex::new(None),
+    });
+    match address {
+        Address::Tcp(addr) => {
+            let listener = TcpListener::bind(addr).map_err(|err| format!("{addr}: {err}"))?;
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    stream.set_nodelay(true).ok();
+                    publisher.attach(Box::new(stream));
+                }
+            });
+        }
+        Address::Unix(path) => {
+            // 前回のソケットが残っていれば消す
+            if fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                fs::remove_file(path).ok();
+            }
+            let listener =
+                UnixListener::bind(path).map_err(|err| format!("{}: {err}", path.display()))?;
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    publisher.attach(Box::new(stream));
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+/// 配信していればゲームの状態を送る
+pub fn publish(game: &Game) {
+    if let Some(publisher) = PUBLISHER.get() {
+        if let Ok(json) = serde_json::to_string(&Frame::from_game(game)) {
+            publisher.broadcast(json.into());
+        }
+    }
+}
+
+/// 配信されているゲームを観戦する
+pub fn watch(address: &Address) -> ! {
+    let stream = match address.connect() {
+        Ok(stream) => stream,
+        Err(err) => {
+            eprintln!("failed to connect: {err}");
+            std::process::exit(1);
+        }
+    };
+    let terminal = Terminal::enter();
+    let game: Arc<Mutex<Option<Game>>> = Arc::new(Mutex::new(None));
+
+    // 端末の大きさが変わったら描画し直す
+    {
+        let game = Arc::clone(&game);
+        term::watch_resize(move || {
+            if let Some(game) = game.lock().unwrap().as_ref() {
+                draw(game);
+            }
+        });
+    }
+
+    // 受信処理
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let frame = serde_json::from_str::<Frame>(&line)
+                .map_err(|err| err.to_string())
+                .and_then(|frame| frame.to_game());
+            match frame {
+                Ok(frame) => {
+                    let mut game = game.lock().unwrap();
+                    draw(&frame);
+                    *game = Some(frame);
+                }
+                Err(err) => {
+                    let _stdout = term::restore();
+                    eprintln!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        // 配信が終わった
+        let _stdout = term::restore();
+        println!("connection closed");
+        quit();
+    });
+
+    // キー入力処理
+    loop {
+        if let Ok(Key::Char('q')) = terminal.getch() {
+            quit();
+        }
+    }
+}
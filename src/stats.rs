@@ -0,0 +1,160 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::BlockKind;
+
+/// 消したライン数ごとの攻撃力(5段以上は4段消しとして扱う)
+pub const ATTACK_TABLE: [usize; 5] = [0, 0, 1, 2, 4];
+/// Tスピンで消したライン数ごとの攻撃力
+pub const TSPIN_ATTACK_TABLE: [usize; 4] = [0, 2, 4, 6];
+/// コンボ数ごとの追加の攻撃力
+pub const COMBO_ATTACK_TABLE: [usize; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+/// パーフェクトクリアの攻撃力
+pub const PERFECT_CLEAR_ATTACK: usize = 10;
+
+/// ゲームの統計
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Stats {
+    #[serde(with = "elapsed")]
+    pub start: Instant, // ゲーム開始時刻
+    pub keys: usize,           // 入力したキーの数
+    pub pieces: usize,         // 置いたブロック数
+    pub lines: usize,          // 消したライン数
+    pub attack: usize,         // 送った火力
+    pub kinds: [usize; 7],     // ブロックの種類ごとの数
+    pub clears: [usize; 5],    // 消したライン数ごとの回数(Tスピンを除く、5段以上は4段)
+    pub tspins: [usize; 4],    // Tスピンで消したライン数ごとの回数
+    pub perfect_clears: usize, // パーフェクトクリアの回数
+    pub max_combo: usize,      // 最大コンボ数
+    pub b2b: bool,             // 直前の消去が4段消しかTスピンか
+    #[serde(default)]
+    pub undos: usize, // 巻き戻した回数
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            keys: 0,
+            pieces: 0,
+            lines: 0,
+            attack: 0,
+            kinds: [0; 7],
+            clears: [0; 5],
+            tspins: [0; 4],
+            perfect_clears: 0,
+            max_combo: 0,
+            b2b: false,
+            undos: 0,
+        }
+    }
+
+    /// ブロックを置いたときの記録
+    pub fn record(
+        &mut self,
+        kind: Option<BlockKind>,
+        line: usize,
+        tspin: bool,
+        perfect_clear: bool,
+        combo: usize,
+    ) {
+        self.pieces += 1;
+        self.lines += line;
+        if let Some(kind) = kind {
+            self.kinds[kind as usize] += 1;
+        }
+        if tspin {
+            self.tspins[line.min(3)] += 1;
+        } else {
+            self.clears[line.min(4)] += 1;
+        }
+        if perfect_clear {
+            self.perfect_clears += 1;
+        }
+        self.max_combo = self.max_combo.max(combo);
+
+        // 火力の計算
+        if line == 0 {
+            return;
+        }
+        let mut attack = if tspin {
+            TSPIN_ATTACK_TABLE[line.min(3)]
+        } else {
+            ATTACK_TABLE[line.min(4)]
+        };
+        let difficult = tspin || line >= 4;
+        if difficult && self.b2b {
+            attack += 1;
+        }
+        self.b2b = difficult;
+        attack += COMBO_ATTACK_TABLE[combo.saturating_sub(1).min(COMBO_ATTACK_TABLE.len() - 1)];
+        if perfect_clear {
+            attack += PERFECT_CLEAR_ATTACK;
+        }
+        self.attack += attack;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// 1秒あたりのブロック数
+    pub fn pps(&self) -> f64 {
+        per(self.pieces as f64, self.elapsed().as_secs_f64())
+    }
+
+    /// 1ブロックあたりのキー入力数
+    pub fn kpp(&self) -> f64 {
+        per(self.keys as f64, self.pieces as f64)
+    }
+
+    /// 1分あたりのライン数
+    pub fn lpm(&self) -> f64 {
+        per(self.lines as f64, self.elapsed().as_secs_f64() / 60.0)
+    }
+
+    /// 1分あたりの火力
+    pub fn apm(&self) -> f64 {
+        per(self.attack as f64, self.elapsed().as_secs_f64() / 60.0)
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 開始時刻を経過時間として保存し、読み込んだ時点から経過時間を引いた時刻に戻す
+mod elapsed {
+    use std::time::{Duration, Instant};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(start: &Instant, serializer: S) -> Result<S::Ok, S::Error> {
+        start.elapsed().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+        let elapsed = Duration::deserialize(deserializer)?;
+        Ok(Instant::now()
+            .checked_sub(elapsed)
+            .unwrap_or_else(Instant::now))
+    }
+}
+
+/// 0で割る場合は0を返す
+fn per(value: f64, unit: f64) -> f64 {
+    if unit > 0.0 {
+        value / unit
+    } else {
+        0.0
+    }
+}
+
+/// 経過時間を `分:秒.小数` で表す
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs_f64();
+    format!("{}:{:05.2}", (secs / 60.0) as u64, secs % 60.0)
+}
@@ -0,0 +1,549 @@
+//! Tetris Bot Protocol (TBP)
+//! https://github.com/tetris-bot-protocol/tbp-spec
+//!
+//! TBPのフィールドは幅10なので、このゲームのフィールドの一番右の列は壁として扱う(`narrow_field`)
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::eval,
+    block::{block_kind, BlockColor, BlockKind, BlockShape, BLOCK_SIZE},
+    field::{narrow_field, Field, EMPTY_FIELD, FIELD_HEIGHT, GUIDELINE_WIDTH},
+    game::{can_hold, hold, is_collision, rotate_shape_right, Game, LockTimes, Position},
+    rules::Rules,
+    stats::Stats,
+};
+
+/// TBPのフィールドの幅
+pub const BOARD_WIDTH: usize = GUIDELINE_WIDTH;
+/// TBPのフィールドの高さ
+pub const BOARD_HEIGHT: usize = 40;
+
+/// フロントエンドからボットへのメッセージ
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FrontendMessage {
+    Rules {},
+    Start(Start),
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: BlockKind,
+    },
+    Stop,
+    Quit,
+    #[serde(other)]
+    Unknown,
+}
+
+/// ボットからフロントエンドへのメッセージ
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+        author: String,
+        features: Vec<String>,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Start {
+    pub hold: Option<BlockKind>,
+    pub queue: Vec<BlockKind>,
+    pub combo: usize,
+    pub back_to_back: bool,
+    pub board: Vec<Vec<Option<Cell>>>,
+}
+
+/// フィールドのセル
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Cell {
+    I,
+    O,
+    T,
+    L,
+    J,
+    S,
+    Z,
+    G,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Move {
+    pub location: Location,
+    pub spin: Spin,
+}
+
+/// ブロックの中心の座標と向き(yは下が0)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(rename = "type")]
+    pub kind: BlockKind,
+    pub orientation: Orientation,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+impl Orientation {
+    const ALL: [Orientation; 4] = [
+        Orientation::North,
+        Orientation::East,
+        Orientation::South,
+        Orientation::West,
+    ];
+
+    /// 北向きの座標を、この向きに回転させる
+    fn rotate(self, (x, y): (i32, i32)) -> (i32, i32) {
+        match self {
+            Orientation::North => (x, y),
+            Orientation::East => (y, -x),
+            Orientation::South => (-x, -y),
+            Orientation::West => (-y, x),
+        }
+    }
+}
+
+/// 北向きのブロックの、中心からの各セルの座標(SRS)
+fn north_cells(kind: BlockKind) -> [(i32, i32); 4] {
+    match kind {
+        BlockKind::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        BlockKind::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        BlockKind::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        BlockKind::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        BlockKind::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        BlockKind::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        BlockKind::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+    }
+}
+
+impl Location {
+    /// このロケーションが占めるセル(TBP座標)
+    pub fn cells(&self) -> [(i32, i32); 4] {
+        north_cells(self.kind).map(|cell| {
+            let (x, y) = self.orientation.rotate(cell);
+            (self.x + x, self.y + y)
+        })
+    }
+}
+
+/// TBP座標からフィールドの座標に変換する
+fn to_field(x: i32, y: i32) -> Option<(usize, usize)> {
+    if !(0..BOARD_WIDTH as i32).contains(&x) || !(0..(FIELD_HEIGHT - 2) as i32).contains(&y) {
+        return None;
+    }
+    Some((x as usize + 2, FIELD_HEIGHT - 3 - y as usize))
+}
+
+/// フィールドの座標からTBP座標に変換する
+fn from_field(x: usize, y: usize) -> (i32, i32) {
+    (x as i32 - 2, (FIELD_HEIGHT - 3 - y) as i32)
+}
+
+/// ブロックが占めるセル(フィールドの座標)を並べて返す
+#[allow(clippy::needless_range_loop)]
+fn occupied(pos: &Position, block: &BlockShape) -> Vec<(usize, usize)> {
+    let mut cells = vec![];
+    for y in 0..BLOCK_SIZE {
+        for x in 0..BLOCK_SIZE {
+            if block[y][x] != block_kind::NONE {
+                cells.push((x + pos.x, y + pos.y));
+            }
+        }
+    }
+    cells.sort_unstable();
+    cells
+}
+
+/// フィールド上のブロックの位置をTBPのロケーションに変換する
+pub fn location_of(pos: &Position, block: &BlockShape) -> Option<Location> {
+    let kind = BlockKind::from_shape(block)?;
+    let mut cells = occupied(pos, block)
+        .into_iter()
+        .map(|(x, y)| from_field(x, y))
+        .collect::<Vec<_>>();
+    cells.sort_unstable();
+    for orientation in Orientation::ALL {
+        let mut offsets = north_cells(kind).map(|cell| orientation.rotate(cell));
+        offsets.sort_unstable();
+        let (x, y) = (cells[0].0 - offsets[0].0, cells[0].1 - offsets[0].1);
+        let location = Location {
+            kind,
+            orientation,
+            x,
+            y,
+        };
+        let mut located = location.cells();
+        located.sort_unstable();
+        if located[..] == cells[..] {
+            return Some(location);
+        }
+    }
+    None
+}
+
+/// TBPのロケーションを、フィールド上のブロックの位置と形に変換する
+pub fn placement_of(location: &Location) -> Option<(Position, BlockShape)> {
+    let mut cells = location
+        .cells()
+        .into_iter()
+        .map(|(x, y)| to_field(x, y))
+        .collect::<Option<Vec<_>>>()?;
+    cells.sort_unstable();
+
+    let mut block = location.kind.shape();
+    for _ in 0..4 {
+        let offsets = occupied(&Position { x: 0, y: 0 }, &block);
+        if let (Some(x), Some(y)) = (
+            cells[0].0.checked_sub(offsets[0].0),
+            cells[0].1.checked_sub(offsets[0].1),
+        ) {
+            let pos = Position { x, y };
+            if occupied(&pos, &block) == cells {
+                return Some((pos, block));
+            }
+        }
+        block = rotate_shape_right(&block);
+    }
+    None
+}
+
+/// フィールドをTBPのボードに変換する
+pub fn board_of(field: &Field) -> Vec<Vec<Option<Cell>>> {
+    let mut board = vec![vec![None; BOARD_WIDTH]; BOARD_HEIGHT];
+    for (y, row) in board.iter_mut().enumerate().take(FIELD_HEIGHT - 2) {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let (fx, fy) = to_field(x as i32, y as i32).unwrap();
+            *cell = cell_of(field[fy][fx]);
+        }
+    }
+    board
+}
+
+/// TBPのボードからフィールドを作る
+pub fn field_of(board: &[Vec<Option<Cell>>]) -> Field {
+    let mut field = EMPTY_FIELD;
+    narrow_field(&mut field);
+    for (y, row) in board.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if let (Some(cell), Some((fx, fy))) = (cell, to_field(x as i32, y as i32)) {
+                field.set(fx, fy, color_of(*cell));
+            }
+        }
+    }
+    field
+}
+
+fn cell_of(color: BlockColor) -> Option<Cell> {
+    match BlockKind::from_color(color) {
+        Some(BlockKind::I) => Some(Cell::I),
+        Some(BlockKind::O) => Some(Cell::O),
+        Some(BlockKind::T) => Some(Cell::T),
+        Some(BlockKind::L) => Some(Cell::L),
+        Some(BlockKind::J) => Some(Cell::J),
+        Some(BlockKind::S) => Some(Cell::S),
+        Some(BlockKind::Z) => Some(Cell::Z),
+        None if color == block_kind::NONE => None,
+        None => Some(Cell::G),
+    }
+}
+
+fn color_of(cell: Cell) -> BlockColor {
+    match cell {
+        Cell::I => BlockKind::I.color(),
+        Cell::O => BlockKind::O.color(),
+        Cell::T => BlockKind::T.color(),
+        Cell::L => BlockKind::L.color(),
+        Cell::J => BlockKind::J.color(),
+        Cell::S => BlockKind::S.color(),
+        Cell::Z => BlockKind::Z.color(),
+        Cell::G => block_kind::GARBAGE,
+    }
+}
+
+/// ゲームの状態をTBPのstartメッセージに変換する
+pub fn start_of(game: &Game) -> Start {
+    let mut queue = vec![];
+    queue.extend(BlockKind::from_shape(&game.block));
+    queue.extend(game.next.iter().filter_map(BlockKind::from_shape));
+    Start {
+        hold: game.hold.as_ref().and_then(BlockKind::from_shape),
+        queue,
+        combo: game.combo,
+        back_to_back: false,
+        board: board_of(&game.field),
+    }
+}
+
+/// ボット側で保持するゲームの状態
+struct BotState {
+    field: Field,
+    hold: Option<BlockKind>,
+    queue: VecDeque<BlockKind>,
+    combo: usize,
+}
+
+impl BotState {
+    fn new(start: Start) -> Self {
+        Self {
+            field: field_of(&start.board),
+            hold: start.hold,
+            queue: start.queue.into(),
+            combo: start.combo,
+        }
+    }
+
+    /// 現在の状態から評価用のゲームを作る
+    fn game(&self) -> Option<Game> {
+        let mut queue = self.queue.iter().map(|kind| kind.shape());
+        let block = queue.next()?;
+        let next: VecDeque<_> = queue.collect();
+        Some(Game {
+            field: self.field,
+            pos: Position::init(),
+            block,
+            hold: self.hold.map(BlockKind::shape),
+            // ホールドが空でネクストもない場合はホールドできない
+            holded: self.hold.is_none() && next.is_empty(),
+            next,
+            next_buf: VecDeque::new(),
+            score: 0,
+            line: 0,
+            combo: self.combo,
+            rng: ChaCha8Rng::seed_from_u64(0),
+            seed: 0,
+            fixed_queue: true,
+            rotated: false,
+            stats: Stats::new(),
+            rules: Rules::default(),
+            events: VecDeque::new(),
+            lock_times: LockTimes::default(),
+        })
+    }
+
+    /// 指定された手を反映する
+    fn play(&mut self, mv: &Move) -> Result<(), String> {
+        let current = *self.queue.front().ok_or("queue is empty")?;
+        if mv.location.kind != current {
+            // ホールドを使った
+            self.queue.pop_front();
+            match self.hold.replace(current) {
+                Some(hold) => self.queue.push_front(hold),
+                None if self.queue.is_empty() => return Err("queue is empty".into()),
+                None => {}
+            }
+            if self.queue.front() != Some(&mv.location.kind) {
+                return Err("piece is not available".into());
+            }
+        }
+        self.queue.pop_front();
+        let (pos, block) = placement_of(&mv.location).ok_or("invalid location")?;
+        for (x, y) in occupied(&pos, &block) {
+            self.field.set(x, y, block[y - pos.y][x - pos.x]);
+        }
+        if self.field.erase_lines() > 0 {
+            self.combo += 1;
+        } else {
+            self.combo = 0;
+        }
+        Ok(())
+    }
+}
+
+/// 指定された手をゲームに反映する(ブロックは固定しない)
+/// return: ホールドによって新しく見えるようになったブロックの数
+#[allow(clippy::result_unit_err)]
+pub fn apply_move(game: &mut Game, mv: &Move) -> Result<usize, ()> {
+    let mut revealed = 0;
+    if BlockKind::from_shape(&game.block) != Some(mv.location.kind) {
+        if !can_hold(game) {
+            return Err(());
+        }
+        if game.hold.is_none() {
+            revealed += 1;
+        }
+        hold(game);
+    }
+    if BlockKind::from_shape(&game.block) != Some(mv.location.kind) {
+        return Err(());
+    }
+    let (pos, block) = placement_of(&mv.location).ok_or(())?;
+    if is_collision(&game.field, &pos, &block) {
+        return Err(());
+    }
+    game.pos = pos;
+    game.block = block;
+    Ok(revealed)
+}
+
+fn send<T: Serialize>(out: &mut impl Write, message: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, message)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+fn info() -> BotMessage {
+    BotMessage::Info {
+        name: env!("CARGO_PKG_NAME").into(),
+        version: env!("CARGO_PKG_VERSION").into(),
+        author: env!("CARGO_PKG_AUTHORS").into(),
+        features: vec![],
+    }
+}
+
+/// TBPのボットとして動作する
+/// `input` からフロントエンドのメッセージを読み、`output` に返事を書く
+pub fn bot(input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut state: Option<BotState> = None;
+
+    send(&mut output, &info())?;
+    for line in input.lines() {
+        let message = match serde_json::from_str::<FrontendMessage>(&line?) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+        match message {
+            FrontendMessage::Rules {} => send(&mut output, &BotMessage::Ready)?,
+            FrontendMessage::Start(start) => state = Some(BotState::new(start)),
+            FrontendMessage::Suggest => {
+                let elite = state
+                    .as_ref()
+                    .and_then(BotState::game)
+                    .map(|game| eval(&game));
+                let moves = elite
+                    .and_then(|game| location_of(&game.pos, &game.block))
+                    .map(|location| Move {
+                        location,
+                        spin: Spin::None,
+                    })
+                    .into_iter()
+                    .collect();
+                send(&mut output, &BotMessage::Suggestion { moves })?;
+            }
+            FrontendMessage::Play { mv } => {
+                if let Some(state) = &mut state {
+                    if let Err(reason) = state.play(&mv) {
+                        send(&mut output, &BotMessage::Error { reason })?;
+                    }
+                }
+            }
+            FrontendMessage::NewPiece { piece } => {
+                if let Some(state) = &mut state {
+                    state.queue.push_back(piece);
+                }
+            }
+            FrontendMessage::Stop => state = None,
+            FrontendMessage::Quit => break,
+            FrontendMessage::Unknown => {}
+        }
+    }
+    Ok(())
+}
+
+/// 外部のTBPボットのプロセス
+pub struct ExternalBot {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExternalBot {
+    /// プログラムを引数を付けて起動し、ボットの準備ができるまで待つ
+    pub fn launch(program: &str, args: &[String]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut bot = Self {
+            child,
+            stdin,
+            stdout,
+        };
+
+        bot.send(&FrontendMessage::Rules {})?;
+        loop {
+            match bot.recv()? {
+                BotMessage::Ready => return Ok(bot),
+                BotMessage::Error { reason } => return Err(io::Error::other(reason)),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: &FrontendMessage) -> io::Result<()> {
+        send(&mut self.stdin, message)
+    }
+
+    /// ボットからのメッセージを1つ受け取る
+    pub fn recv(&mut self) -> io::Result<BotMessage> {
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if let Ok(message) = serde_json::from_str(&line) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// 次の手を提案してもらう
+    pub fn suggest(&mut self) -> io::Result<Option<Move>> {
+        self.send(&FrontendMessage::Suggest)?;
+        loop {
+            match self.recv()? {
+                BotMessage::Suggestion { moves } => return Ok(moves.into_iter().next()),
+                BotMessage::Error { reason } => return Err(io::Error::other(reason)),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for ExternalBot {
+    fn drop(&mut self) {
+        self.send(&FrontendMessage::Quit).ok();
+        self.child.wait().ok();
+    }
+}
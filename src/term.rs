@@ -0,0 +1,256 @@
+//! 端末の設定と画面の配置
+//!
+//! ゲーム中は代替画面に切り替えて端末をrawモードにし、終了、パニック、
+//! SIGINT/SIGTERMで元に戻す。Ctrl-Zで中断したときも一度元に戻し、再開したら描画し直す
+//!
+//! 画面の座標は左上を `(1, 1)` とした描画領域の中の位置で指定し、
+//! 端末の中央に描画領域を置くようにずらしてからカーソルを移動する
+
+use std::{
+    env,
+    io::{self, StdoutLock, Write},
+    panic, ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use getch_rs::{Getch, Key};
+
+/// 描画領域の幅(フィールド、ホールド、ネクスト、統計)
+pub const MIN_WIDTH: usize = 56;
+/// 描画領域の高さ(フィールドとメッセージの行)
+pub const MIN_HEIGHT: usize = 23;
+
+/// 描画領域の高さ(モードやネクストの数で増える)
+static HEIGHT: AtomicUsize = AtomicUsize::new(MIN_HEIGHT);
+/// 描画領域の左上の位置(端末の座標から1を引いたもの)
+static TOP: AtomicUsize = AtomicUsize::new(0);
+static LEFT: AtomicUsize = AtomicUsize::new(0);
+/// 端末が描画領域より小さいか
+static TOO_SMALL: AtomicBool = AtomicBool::new(false);
+/// 端末の大きさが変わったか
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+/// 元の端末の設定とゲーム中の端末の設定
+static TERMIOS: OnceLock<(libc::termios, libc::termios)> = OnceLock::new();
+/// ゲーム用の画面になっているか
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// 代替画面に切り替え、画面をクリアしてカーソルを隠す
+const ENTER: &str = "\x1b[?1049h\x1b[2J\x1b[H\x1b[?25l";
+/// 色を戻し、カーソルを表示して元の画面に戻る
+const LEAVE: &str = "\x1b[0m\x1b[?25h\x1b[?1049l";
+
+/// ゲーム中の端末
+/// 作ると代替画面とrawモードに切り替え、捨てると元に戻す
+pub struct Terminal {
+    getch: Getch,
+}
+
+impl Terminal {
+    pub fn enter() -> Self {
+        let original = tcgetattr();
+        // エコーと行単位の入力を止める
+        let getch = Getch::new();
+        // Ctrl-CとCtrl-Zはシグナルとして受け取る
+        let mut raw = tcgetattr();
+        raw.c_lflag |= libc::ISIG;
+        TERMIOS.set((original, raw)).ok();
+
+        // パニックしたら元に戻してからメッセージを表示する
+        // 描画や入力のスレッドが止まるとゲームを続けられないので、そのまま終了する
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _stdout = restore();
+            default_hook(info);
+            std::process::exit(101);
+        }));
+
+        // シグナルは全てのスレッドで止めておき、専用のスレッドで受け取る
+        let signals = signal_set(&[
+            libc::SIGINT,
+            libc::SIGTERM,
+            libc::SIGTSTP,
+            libc::SIGWINCH,
+        ]);
+        unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut()) };
+        thread::spawn(move || handle_signals(signals));
+
+        activate();
+        Self { getch }
+    }
+
+    /// キー入力を1つ読む
+    pub fn getch(&self) -> Result<Key, io::Error> {
+        self.getch.getch()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        drop(restore());
+    }
+}
+
+fn tcgetattr() -> libc::termios {
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) };
+    termios
+}
+
+/// ゲーム用の画面にする
+fn activate() {
+    if let Some((_, raw)) = TERMIOS.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, raw) };
+    }
+    print!("{ENTER}");
+    io::stdout().flush().ok();
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// 描画途中の出力を書き出してから元の画面に戻す
+/// 元の画面では描画領域をずらさない
+/// 返したロックを持っている間は他のスレッドから描画されない
+pub fn restore() -> StdoutLock<'static> {
+    let mut stdout = io::stdout().lock();
+    if ACTIVE.swap(false, Ordering::Relaxed) {
+        write!(stdout, "{LEAVE}").ok();
+        stdout.flush().ok();
+        if let Some((original, _)) = TERMIOS.get() {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, original) };
+        }
+    }
+    TOP.store(0, Ordering::Relaxed);
+    LEFT.store(0, Ordering::Relaxed);
+    stdout
+}
+
+fn signal_set(signals: &[libc::c_int]) -> libc::sigset_t {
+    let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::sigemptyset(&mut set) };
+    for &signal in signals {
+        unsafe { libc::sigaddset(&mut set, signal) };
+    }
+    set
+}
+
+/// シグナルを待って処理する
+fn handle_signals(signals: libc::sigset_t) {
+    loop {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            continue;
+        }
+        match signal {
+            libc::SIGWINCH => RESIZED.store(true, Ordering::Relaxed),
+            libc::SIGTSTP => suspend(),
+            _ => {
+                // SIGINT/SIGTERMで終了したことがわかる終了コードにする
+                let _stdout = restore();
+                std::process::exit(128 + signal);
+            }
+        }
+    }
+}
+
+/// 元の画面に戻してから中断し、再開したらゲーム用の画面に戻して描画し直す
+fn suspend() {
+    let stdout = restore();
+    let tstp = signal_set(&[libc::SIGTSTP]);
+    unsafe {
+        // このスレッドだけ止めるのをやめて、本来の動作で中断する
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &tstp, ptr::null_mut());
+        libc::raise(libc::SIGTSTP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &tstp, ptr::null_mut());
+    }
+    activate();
+    RESIZED.store(true, Ordering::Relaxed);
+    drop(stdout);
+}
+
+/// 端末の大きさ(幅, 高さ)
+/// 取得できない場合は `COLUMNS` と `LINES`、それもなければ80x24とする
+pub fn size() -> (usize, usize) {
+    let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut winsize) };
+    if result == 0 && winsize.ws_col > 0 && winsize.ws_row > 0 {
+        return (winsize.ws_col as usize, winsize.ws_row as usize);
+    }
+    let var = |name: &str, default: usize| {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    (var("COLUMNS", 80), var("LINES", 24))
+}
+
+/// 端末の大きさから描画領域の位置を計算し直す
+pub fn update_layout() {
+    let (width, height) = size();
+    let rows = HEIGHT.load(Ordering::Relaxed);
+    TOO_SMALL.store(width < MIN_WIDTH || height < rows, Ordering::Relaxed);
+    LEFT.store(width.saturating_sub(MIN_WIDTH) / 2, Ordering::Relaxed);
+    TOP.store(height.saturating_sub(rows) / 2, Ordering::Relaxed);
+}
+
+/// 描画領域に必要な行数を増やす
+/// 増えた場合は次の [`refresh`] で描画し直す
+pub fn reserve_rows(rows: usize) {
+    if HEIGHT.fetch_max(rows, Ordering::Relaxed) < rows {
+        RESIZED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 端末が描画領域より小さいか
+pub fn too_small() -> bool {
+    TOO_SMALL.load(Ordering::Relaxed)
+}
+
+/// 描画領域の中の位置へカーソルを移動するエスケープシーケンス
+pub fn goto(row: usize, col: usize) -> String {
+    format!(
+        "\x1b[{};{}H",
+        row + TOP.load(Ordering::Relaxed),
+        col + LEFT.load(Ordering::Relaxed)
+    )
+}
+
+/// 今の行のまま、描画領域の中の列へカーソルを移動するエスケープシーケンス
+pub fn column(col: usize) -> String {
+    format!("\x1b[{}G", col + LEFT.load(Ordering::Relaxed))
+}
+
+/// 大きさが変わっていれば描画領域を計算し直して画面をクリアする
+/// 描画の前に呼ぶ
+pub fn refresh() {
+    if RESIZED.swap(false, Ordering::Relaxed) {
+        update_layout();
+        println!("\x1b[2J");
+    }
+}
+
+/// 端末が小さすぎることを表示する
+pub fn draw_too_small() {
+    let (width, height) = size();
+    println!(
+        "\x1b[0m\x1b[Hterminal too small\x1b[K\r\nneed {MIN_WIDTH}x{}, have {width}x{height}\x1b[J",
+        HEIGHT.load(Ordering::Relaxed)
+    );
+}
+
+/// 端末の大きさが変わったら `redraw` で描画し直す
+/// `redraw` は描画の前に [`refresh`] を呼ぶこと
+pub fn watch_resize(redraw: impl Fn() + Send + 'static) {
+    update_layout();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(50));
+        if RESIZED.load(Ordering::Relaxed) {
+            redraw();
+        }
+    });
+}
@@ -0,0 +1,326 @@
+//! 色とセルの文字のテーマ
+//!
+//! テーマは組み込みのものを名前で選ぶか、JSONファイルから読み込む
+//!
+//! ```json
+//! {
+//!     "base": "colorblind",
+//!     "colors": { "I": "#00a0ff", "ghost": "#202020" },
+//!     "glyphs": { "block": "██" }
+//! }
+//! ```
+//!
+//! - `base` は元にする組み込みのテーマ(省略すると `default`)
+//! - `colors` と `glyphs` のキーは `empty`, `wall`, `ghost`, `I`, `O`, `S`, `Z`, `J`, `L`, `T`, `garbage`, `hint`
+//! - `glyphs` の `block` は全てのブロックのセルをまとめて設定する
+
+use std::{collections::BTreeMap, env, fs, path::Path, sync::OnceLock};
+
+use clap::ValueEnum;
+use rust_tetris_tutorial::{block::block_kind, pieces};
+use serde::Deserialize;
+
+/// セルの種類の数
+const CELL_KINDS: usize = 12;
+/// セルの種類の名前(block_kindの順)
+const CELL_NAMES: [&str; CELL_KINDS] = [
+    "empty", "wall", "ghost", "I", "O", "S", "Z", "J", "L", "T", "garbage", "hint",
+];
+/// 組み込みのテーマの名前
+pub const BUILTIN_THEMES: [&str; 4] = ["default", "colorblind", "pastel", "classic"];
+
+type Rgb = (u8, u8, u8);
+
+/// 端末の色数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorDepth {
+    /// 環境変数から判定する
+    Auto,
+    /// 24ビットカラー
+    Truecolor,
+    /// 256色
+    #[value(name = "256")]
+    Ansi256,
+    /// 16色
+    #[value(name = "16")]
+    Ansi16,
+    /// 色なし
+    Mono,
+}
+
+impl ColorDepth {
+    /// `NO_COLOR`、`COLORTERM`、`TERM` から端末の色数を判定する
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorDepth::Mono;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::Truecolor;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        if term.is_empty() || term == "dumb" {
+            ColorDepth::Mono
+        } else if term.contains("truecolor") || term.contains("direct") {
+            ColorDepth::Truecolor
+        } else if term.contains("256") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+/// テーマ
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub colors: [Rgb; CELL_KINDS],    // 背景色
+    pub glyphs: [String; CELL_KINDS], // セルの文字(2文字分の幅)
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::builtin("default").unwrap()
+    }
+}
+
+impl Theme {
+    /// 組み込みのテーマ
+    pub fn builtin(name: &str) -> Option<Self> {
+        let colors = match name {
+            "default" => [
+                (0, 0, 0),       // 何もなし
+                (127, 127, 127), // 壁
+                (0, 0, 0),       // ゴースト
+                (0, 0, 255),     // I
+                (0, 255, 0),     // O
+                (0, 255, 255),   // S
+                (255, 0, 0),     // Z
+                (255, 0, 255),   // J
+                (255, 127, 0),   // L
+                (255, 255, 0),   // T
+                (127, 127, 127), // お邪魔ブロック
+                (63, 63, 63),    // ヒント
+            ],
+            // Okabe-Itoの色覚多様性に配慮した配色
+            "colorblind" => [
+                (0, 0, 0),
+                (127, 127, 127),
+                (0, 0, 0),
+                (86, 180, 233),
+                (240, 228, 66),
+                (0, 158, 115),
+                (213, 94, 0),
+                (0, 114, 178),
+                (230, 159, 0),
+                (204, 121, 167),
+                (127, 127, 127),
+                (63, 63, 63),
+            ],
+            "pastel" => [
+                (30, 30, 30),
+                (120, 120, 120),
+                (30, 30, 30),
+                (150, 200, 255),
+                (255, 240, 150),
+                (170, 230, 170),
+                (255, 160, 160),
+                (160, 170, 255),
+                (255, 200, 140),
+                (220, 170, 255),
+                (150, 150, 150),
+                (70, 70, 70),
+            ],
+            // ガイドラインに近い配色
+            "classic" => [
+                (0, 0, 0),
+                (127, 127, 127),
+                (0, 0, 0),
+                (0, 255, 255),
+                (255, 255, 0),
+                (0, 255, 0),
+                (255, 0, 0),
+                (0, 0, 255),
+                (255, 127, 0),
+                (160, 0, 240),
+                (127, 127, 127),
+                (63, 63, 63),
+            ],
+            _ => return None,
+        };
+        let glyphs = [
+            "  ", "__", "[]", "__", "__", "__", "__", "__", "__", "__", "[]", "<>",
+        ];
+        Some(Self {
+            colors,
+            glyphs: glyphs.map(String::from),
+        })
+    }
+
+    /// 組み込みのテーマの名前か、テーマファイルのパスから読み込む
+    pub fn load(name: &str) -> Result<Self, String> {
+        if let Some(theme) = Self::builtin(name) {
+            return Ok(theme);
+        }
+        let path = Path::new(name);
+        if !path.exists() {
+            return Err(format!(
+                "unknown theme `{name}` (built-in themes: {})",
+                BUILTIN_THEMES.join(", ")
+            ));
+        }
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// JSON形式のテーマを読み込む
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: ThemeFile = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        let base = file.base.as_deref().unwrap_or("default");
+        let mut theme =
+            Self::builtin(base).ok_or_else(|| format!("unknown base theme `{base}`"))?;
+        for (name, color) in &file.colors {
+            theme.colors[cell_index(name)?] = parse_rgb(color)?;
+        }
+        if let Some(glyph) = file.glyphs.get("block") {
+            for color in block_kind::I..=block_kind::T {
+                theme.glyphs[color] = glyph.clone();
+            }
+        }
+        for (name, glyph) in file.glyphs.iter().filter(|(name, _)| *name != "block") {
+            theme.glyphs[cell_index(name)?] = glyph.clone();
+        }
+        Ok(theme)
+    }
+}
+
+/// テーマファイル
+#[derive(Deserialize)]
+struct ThemeFile {
+    base: Option<String>,
+    #[serde(default)]
+    colors: BTreeMap<String, String>,
+    #[serde(default)]
+    glyphs: BTreeMap<String, String>,
+}
+
+fn cell_index(name: &str) -> Result<usize, String> {
+    CELL_NAMES
+        .iter()
+        .position(|&cell| cell == name)
+        .ok_or_else(|| format!("unknown cell `{name}`"))
+}
+
+/// `#rrggbb` 形式の色を読み込む
+fn parse_rgb(text: &str) -> Result<Rgb, String> {
+    let hex = text
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.is_ascii())
+        .ok_or_else(|| format!("invalid color `{text}`"))?;
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..(i + 2)], 16).map_err(|_| format!("invalid color `{text}`"))
+    };
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// 256色の近い色
+fn ansi256(rgb: Rgb) -> u8 {
+    let (r, g, b) = rgb;
+    if r == g && g == b {
+        // グレースケール
+        return match r {
+            0..=7 => 16,
+            248..=255 => 231,
+            r => 232 + ((r as u16 - 8) * 24 / 240) as u8,
+        };
+    }
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// 16色の近い色の背景色のコード
+fn ansi16(rgb: Rgb) -> u8 {
+    const PALETTE: [Rgb; 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let distance = |(r, g, b): Rgb| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+    let index = (0..16).min_by_key(|&i| distance(PALETTE[i])).unwrap() as u8;
+    if index < 8 {
+        40 + index
+    } else {
+        100 + index - 8
+    }
+}
+
+/// 色なしの場合のセルの文字(ブロックの種類を文字で区別する)
+const MONO_GLYPHS: [&str; CELL_KINDS] = [
+    "  ", "##", "::", "II", "OO", "SS", "ZZ", "JJ", "LL", "TT", "XX", "<>",
+];
+
+/// 各セルを描画する文字列
+static CELLS: OnceLock<(ColorDepth, Vec<String>)> = OnceLock::new();
+
+/// テーマと色数からセルの描画に使う文字列を作る
+fn render(theme: &Theme, depth: ColorDepth) -> (ColorDepth, Vec<String>) {
+    let depth = match depth {
+        ColorDepth::Auto => ColorDepth::detect(),
+        depth => depth,
+    };
+    let cells = (0..CELL_KINDS)
+        .map(|i| {
+            let (r, g, b) = theme.colors[i];
+            let glyph = &theme.glyphs[i];
+            match depth {
+                ColorDepth::Truecolor | ColorDepth::Auto => {
+                    format!("\x1b[48;2;{r:03};{g:03};{b:03}m{glyph}")
+                }
+                ColorDepth::Ansi256 => format!("\x1b[48;5;{}m{glyph}", ansi256((r, g, b))),
+                ColorDepth::Ansi16 => format!("\x1b[{}m{glyph}", ansi16((r, g, b))),
+                ColorDepth::Mono => format!("\x1b[0m{}", MONO_GLYPHS[i]),
+            }
+        })
+        .collect();
+    (depth, cells)
+}
+
+/// 使うテーマと色数を設定する
+/// 設定しない場合は標準のテーマで色数を自動で判定する
+pub fn init(theme: &Theme, depth: ColorDepth) {
+    CELLS.set(render(theme, depth)).ok();
+}
+
+fn cells() -> &'static (ColorDepth, Vec<String>) {
+    CELLS.get_or_init(|| render(&Theme::default(), ColorDepth::Auto))
+}
+
+/// セルを描画する文字列
+/// ピースセットで定義したブロックはそのブロックの `color` で描画する
+pub fn cell(color: usize) -> &'static str {
+    &cells().1[pieces::current().palette(color)]
+}
+
+/// 文字を暗くする(灰色にする)エスケープシーケンス
+pub fn dim() -> &'static str {
+    match cells().0 {
+        ColorDepth::Mono => "\x1b[2m",
+        _ => "\x1b[90m",
+    }
+}